@@ -19,8 +19,24 @@ async fn amain() {
 
     let mut frame_arena = Arena::default();
 
-    let mut sim = Simulation::new(&frame_arena);
+    let scenario = match std::fs::read_to_string(SCENARIO_PATH) {
+        Ok(scenario) => scenario,
+        Err(err) => {
+            eprintln!("Could not read the scenario {SCENARIO_PATH}: {err}");
+            return;
+        }
+    };
+    let mut sim = match Simulation::from_scenario(&scenario, WORLD_SEED, &frame_arena) {
+        Ok(sim) => sim,
+        Err(errors) => {
+            eprintln!("Invalid scenario:\n{errors}");
+            return;
+        }
+    };
     frame_arena.reset();
+    sim.start_recording(&scenario);
+    // Closing the window goes through the loop below, so that the recording is written out
+    mq::prevent_quit();
 
     let mut gui = gui::Gui::new();
     egui_macroquad::cfg(|ctx| gui.setup(ctx));
@@ -30,7 +46,8 @@ async fn amain() {
 
     loop {
         frame_arena.reset();
-        if mq::is_key_pressed(mq::KeyCode::Escape) {
+        if mq::is_key_pressed(mq::KeyCode::Escape) || mq::is_quit_requested() {
+            finish_recording(&mut sim);
             break;
         }
 
@@ -77,6 +94,23 @@ async fn amain() {
             if mq::is_key_pressed(mq::KeyCode::Space) {
                 request.end_turn = true;
             }
+
+            if mq::is_key_pressed(mq::KeyCode::F5)
+                && let Err(err) = quick_save(&sim)
+            {
+                eprintln!("Could not save: {err}");
+            }
+
+            if mq::is_key_pressed(mq::KeyCode::F9) {
+                match quick_load() {
+                    // Logs replay from a scenario, so the session recorded so far ends here
+                    Ok(loaded) => {
+                        finish_recording(&mut sim);
+                        sim = loaded;
+                    }
+                    Err(err) => eprintln!("Could not load: {err}"),
+                }
+            }
         }
 
         mq::clear_background(mq::LIGHTGRAY);
@@ -102,6 +136,40 @@ async fn amain() {
     }
 }
 
+const WORLD_SEED: u64 = 2704;
+
+const SCENARIO_PATH: &str = "assets/scenarios/rheged.toml";
+
+const QUICK_SAVE_PATH: &str = "quicksave.ron";
+
+fn quick_save(sim: &Simulation) -> anyhow::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(QUICK_SAVE_PATH)?);
+    sim.save(&mut file)?;
+    Ok(())
+}
+
+fn quick_load() -> anyhow::Result<Simulation> {
+    let file = std::io::BufReader::new(std::fs::File::open(QUICK_SAVE_PATH)?);
+    Ok(Simulation::load(file)?)
+}
+
+const COMMAND_LOG_PATH: &str = "last_session.log.ron";
+
+fn save_command_log(log: &CommandLog) -> anyhow::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(COMMAND_LOG_PATH)?);
+    log.save(&mut file)?;
+    Ok(())
+}
+
+/// Stops recording the simulation, if it was, and writes out the log of the session
+fn finish_recording(sim: &mut Simulation) {
+    if let Some(log) = sim.stop_recording()
+        && let Err(err) = save_command_log(&log)
+    {
+        eprintln!("Could not save the command log: {err}");
+    }
+}
+
 fn populate_board(board: &mut board::Board, view: &SimView) {
     board.clear();
     let mut ids = Vec::with_capacity(view.map_items.len());
@@ -130,7 +198,8 @@ fn populate_board(board: &mut board::Board, view: &SimView) {
         let name = if show_name { item.name.as_str() } else { "" };
         let pos = mq::Vec2::new(item.pos.x, item.pos.y);
 
-        let font_size = if is_big { 24 } else { 18 };
+        let font_size = if is_big { 24. } else { 18. } * item.label_scale;
+        let font_size = font_size.round() as u16;
 
         let texture = if item.image.is_empty() {
             None
@@ -36,6 +36,9 @@ impl Gui {
         if let Some(list) = objects.root.try_list("actions") {
             actions_ui(ctx, list, &mut outputs);
         }
+        if let Some(list) = objects.root.try_list("events") {
+            events_ui(ctx, list, &mut outputs);
+        }
         outputs
     }
 }
@@ -71,9 +74,30 @@ fn object_ui(ctx: &egui::Context, obj: &Object, outputs: &mut Outputs) {
                     ("Kind", "kind"),
                     ("Faction", "faction"),
                     ("Reign", "reign"),
+                    ("Ruler", "ruler"),
+                    ("Succession", "succession"),
+                    ("Culture", "culture"),
+                    ("Religion", "religion"),
+                    ("Dynasty", "dynasty"),
+                    ("Sex", "sex"),
+                    ("Age", "age"),
+                    ("Born", "born"),
+                    ("Health", "health"),
+                    ("Destination", "destination"),
+                    ("Cohesion", "cohesion"),
+                    ("Population", "population"),
+                    ("Change", "population_change"),
                 ];
                 field_table(ui, "overview-table", &table, obj);
 
+                if let Some(list) = obj.try_list("aspects") {
+                    let shares: Vec<_> = list
+                        .iter()
+                        .map(|aspect| format!("{} {}", aspect.txt("share"), aspect.txt("name")))
+                        .collect();
+                    ui.label(shares.join(", "));
+                }
+
                 if obj.flag("can_make_active_agent") {
                     if ui.small_button("Make Active Agent").clicked() {
                         outputs.make_active_agent = Some(id);
@@ -95,6 +119,30 @@ fn object_ui(ctx: &egui::Context, obj: &Object, outputs: &mut Outputs) {
                 rows_table(ui, "people-here-grid", &rows, list, outputs, 80.);
             }
 
+            for (key, heading) in [
+                ("parents", "Parents"),
+                ("spouse", "Spouse"),
+                ("children", "Children"),
+                ("members", "Members"),
+                ("holds", "Holds"),
+                ("allies", "Allies"),
+                ("followers", "Followers"),
+            ] {
+                if let Some(list) = obj.try_list(key) {
+                    ui.separator();
+                    ui.heading(heading);
+                    let rows = [Row {
+                        label: "Name",
+                        primary: "name",
+                        id: "id",
+                        width: 160.,
+                        ..Default::default()
+                    }];
+
+                    rows_table(ui, &format!("{key}-grid"), &rows, list, outputs, 80.);
+                }
+            }
+
             if let Some(list) = obj.try_list("cards_here") {
                 ui.separator();
                 ui.heading("Cards Here");
@@ -108,6 +156,98 @@ fn object_ui(ctx: &egui::Context, obj: &Object, outputs: &mut Outputs) {
 
                 rows_table(ui, "cards-here-grid", &rows, list, outputs, 80.);
             }
+
+            if let Some(list) = obj.try_list("parties_here") {
+                ui.separator();
+                ui.heading("Parties Here");
+                let rows = [Row {
+                    label: "Name",
+                    primary: "name",
+                    id: "id",
+                    width: 160.,
+                    ..Default::default()
+                }];
+
+                rows_table(ui, "parties-here-grid", &rows, list, outputs, 80.);
+            }
+
+            if let Some(list) = obj.try_list("relations") {
+                ui.separator();
+                ui.heading("Diplomacy");
+                let rows = [
+                    Row {
+                        label: "Realm",
+                        primary: "name",
+                        id: "id",
+                        width: 120.,
+                        ..Default::default()
+                    },
+                    Row {
+                        label: "State",
+                        primary: "state",
+                        width: 60.,
+                        ..Default::default()
+                    },
+                    Row {
+                        label: "Opinion",
+                        primary: "score",
+                        width: 50.,
+                        ..Default::default()
+                    },
+                ];
+
+                rows_table(ui, "relations-grid", &rows, list, outputs, 60.);
+            }
+
+            if let Some(list) = obj.try_list("population_by_culture") {
+                ui.separator();
+                ui.heading("Population");
+                let rows = [
+                    Row {
+                        label: "Culture",
+                        primary: "name",
+                        width: 80.,
+                        ..Default::default()
+                    },
+                    Row {
+                        label: "People",
+                        primary: "amount",
+                        width: 60.,
+                        ..Default::default()
+                    },
+                ];
+
+                rows_table(ui, "population-grid", &rows, list, outputs, 40.);
+            }
+
+            if let Some(list) = obj.try_list("stockpile") {
+                ui.separator();
+                ui.heading("Stockpile");
+                let tooltip = &[("Shortfall", "shortfall")];
+                let rows = [
+                    Row {
+                        label: "Good",
+                        primary: "name",
+                        tooltip,
+                        width: 80.,
+                        ..Default::default()
+                    },
+                    Row {
+                        label: "Amount",
+                        primary: "amount",
+                        width: 60.,
+                        ..Default::default()
+                    },
+                    Row {
+                        label: "Per Turn",
+                        primary: "flow",
+                        width: 60.,
+                        ..Default::default()
+                    },
+                ];
+
+                rows_table(ui, "stockpile-grid", &rows, list, outputs, 60.);
+            }
         });
 }
 
@@ -127,6 +267,26 @@ fn actions_ui(ctx: &egui::Context, list: &[Object], outputs: &mut Outputs) {
         });
 }
 
+fn events_ui(ctx: &egui::Context, list: &[Object], outputs: &mut Outputs) {
+    egui::Window::new("Events")
+        .default_open(false)
+        .default_height(200.)
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical()
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for event in list {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("Turn {}: {}", event.txt("turn"), event.txt("text")));
+                            for subject in event.list("subjects") {
+                                entity_button(ui, subject, 80., outputs);
+                            }
+                        });
+                    }
+                });
+        });
+}
+
 fn field_table(ui: &mut egui::Ui, grid_id: &str, table: &[(&str, &str)], obj: &Object) {
     egui::Grid::new(grid_id).show(ui, |ui| {
         for &(label, field) in table {
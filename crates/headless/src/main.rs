@@ -0,0 +1,326 @@
+//! Runs the simulation without a display, advancing it a number of turns and printing a
+//! summary of the resulting state.
+//!
+//! Options:
+//! - `--scenario <path>`: scenario to create the world from (default: the one in `assets`)
+//! - `--seed <n>`: seed of a world created from a scenario (default: 2704)
+//! - `--turns <n>`: number of turns to advance (default: 10)
+//! - `--script <path>`: actions to perform along the way, see `script::Script`
+//! - `--load <path>`: start from a save instead of a scenario
+//! - `--replay <path>`: start by replaying a command log, checking it has not diverged
+//! - `--save <path>`: save the final state
+//! - `--record <path>`: write a command log of the run
+//! - `--check-save`: check that saving and loading the final state leaves it unchanged
+//! - `--timings`: print how long each phase of the turn took, summed over the run
+//! - `--ai-report`: print the options every AI agent weighed up, after each turn
+use std::time::Duration;
+
+use anyhow::{Context, anyhow, bail};
+use simulation::*;
+use util::arena::Arena;
+
+mod script;
+use script::{Command, Script};
+
+const DEFAULT_SCENARIO: &str = "assets/scenarios/rheged.toml";
+const DEFAULT_SEED: u64 = 2704;
+const DEFAULT_TURNS: usize = 10;
+
+#[derive(Default)]
+struct Options {
+    scenario: Option<String>,
+    seed: Option<u64>,
+    turns: Option<usize>,
+    script: Option<String>,
+    load: Option<String>,
+    replay: Option<String>,
+    save: Option<String>,
+    record: Option<String>,
+    check_save: bool,
+    timings: bool,
+    ai_report: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Options> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("missing value for '{arg}'"))
+            };
+            match arg.as_str() {
+                "--scenario" => options.scenario = Some(value()?),
+                "--seed" => options.seed = Some(value()?.parse().context("--seed")?),
+                "--turns" => options.turns = Some(value()?.parse().context("--turns")?),
+                "--script" => options.script = Some(value()?),
+                "--load" => options.load = Some(value()?),
+                "--replay" => options.replay = Some(value()?),
+                "--save" => options.save = Some(value()?),
+                "--record" => options.record = Some(value()?),
+                "--check-save" => options.check_save = true,
+                "--timings" => options.timings = true,
+                "--ai-report" => options.ai_report = true,
+                _ => bail!("unknown argument '{arg}'"),
+            }
+        }
+
+        let starts = [&options.scenario, &options.load, &options.replay];
+        if starts.iter().filter(|x| x.is_some()).count() > 1 {
+            bail!("--scenario, --load and --replay are mutually exclusive");
+        }
+        if options.record.is_some() && (options.load.is_some() || options.replay.is_some()) {
+            bail!("--record only works on a world created from a scenario");
+        }
+        if options.seed.is_some() && (options.load.is_some() || options.replay.is_some()) {
+            bail!("--seed only works on a world created from a scenario");
+        }
+        Ok(options)
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let options = Options::parse(std::env::args().skip(1))?;
+
+    let script = match &options.script {
+        Some(path) => {
+            let source = std::fs::read_to_string(path).with_context(|| path.to_string())?;
+            Script::parse(&source)?
+        }
+        None => Script { lines: vec![] },
+    };
+
+    let mut arena = Arena::default();
+    let sim = if let Some(path) = &options.load {
+        let file = std::fs::File::open(path).with_context(|| path.to_string())?;
+        Simulation::load(std::io::BufReader::new(file))?
+    } else if let Some(path) = &options.replay {
+        let file = std::fs::File::open(path).with_context(|| path.to_string())?;
+        let log = CommandLog::load(std::io::BufReader::new(file))?;
+        let sim = log.replay(&mut arena)?;
+        println!("Replayed {} logged ticks", log.len());
+        sim
+    } else {
+        let path = options.scenario.as_deref().unwrap_or(DEFAULT_SCENARIO);
+        let source = std::fs::read_to_string(path).with_context(|| path.to_string())?;
+        let seed = options.seed.unwrap_or(DEFAULT_SEED);
+        let mut sim = Simulation::from_scenario(&source, seed, &arena)?;
+        if options.record.is_some() {
+            sim.start_recording(&source);
+        }
+        sim
+    };
+    arena.reset();
+
+    let mut runner = Runner::new(sim, arena);
+    for _ in 0..options.turns.unwrap_or(DEFAULT_TURNS) {
+        let turn = runner.sim.turn_number();
+        for line in script.at_turn(turn) {
+            runner
+                .perform(&line.command)
+                .with_context(|| format!("script line {}", line.number))?;
+        }
+        runner.tick(TickRequest {
+            end_turn: true,
+            ..Default::default()
+        });
+        if options.ai_report {
+            println!("AI agents on turn {turn}:");
+            print!("{}", runner.sim.ai_report());
+        }
+    }
+
+    let mut sim = runner.sim;
+    print!("{}", sim.summary());
+
+    if options.timings {
+        print_timings(&runner.timings);
+    }
+
+    if let Some(path) = &options.record {
+        let log = sim.stop_recording().expect("recording was started");
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        log.save(&mut file)?;
+    }
+
+    if let Some(path) = &options.save {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        sim.save(&mut file)?;
+    }
+
+    if options.check_save {
+        check_save(&mut sim, &mut runner.arena)?;
+        println!("Save round trip OK");
+    }
+
+    Ok(())
+}
+
+struct Runner {
+    sim: Simulation,
+    arena: Arena,
+    view: SimView,
+    /// Time spent in each phase of the turn so far, in phase order
+    timings: Vec<(&'static str, Duration)>,
+}
+
+impl Runner {
+    fn new(sim: Simulation, arena: Arena) -> Self {
+        let mut runner = Self {
+            sim,
+            arena,
+            view: SimView::default(),
+            timings: vec![],
+        };
+        runner.tick(TickRequest::default());
+        runner
+    }
+
+    fn tick(&mut self, mut request: TickRequest) {
+        request.view.enabled = true;
+        self.view = self.sim.tick(request, &self.arena);
+        self.arena.reset();
+
+        for timing in &self.view.turn_timings {
+            match self
+                .timings
+                .iter_mut()
+                .find(|(phase, _)| *phase == timing.phase)
+            {
+                Some((_, total)) => *total += timing.duration,
+                None => self.timings.push((timing.phase, timing.duration)),
+            }
+        }
+    }
+
+    fn perform(&mut self, command: &Command) -> anyhow::Result<()> {
+        let mut request = TickRequest::default();
+        match command {
+            Command::Select(tag) => {
+                let id = self.sim.lookup(tag);
+                if !id.is_valid() {
+                    bail!("no entity tagged '{tag}'");
+                }
+                request.interacted_with_object = Some(id);
+            }
+            Command::Pick(list, n) => {
+                let entry = n
+                    .checked_sub(1)
+                    .and_then(|idx| self.view.selected.list(list).get(idx))
+                    .ok_or_else(|| anyhow!("the selected entity has no entry {n} in '{list}'"))?;
+                request.interacted_with_object = Some(entry.id("id"));
+            }
+            Command::Activate => {
+                let id = self.view.selected.id("id");
+                if !self.view.selected.flag("can_make_active_agent") {
+                    bail!("'{}' cannot be made active", self.view.selected.txt("name"));
+                }
+                request.make_active = Some(id);
+            }
+            Command::Action(name) => {
+                let action = self
+                    .view
+                    .root
+                    .list("actions")
+                    .iter()
+                    .find(|action| action.txt("name") == name)
+                    .ok_or_else(|| anyhow!("no available action named '{name}'"))?;
+                request.interacted_with_object = Some(action.id("id"));
+            }
+        }
+        self.tick(request);
+        // Actions available after an interaction are only worked out on the tick after it, as
+        // it happens from one frame to the next in the game
+        self.tick(TickRequest::default());
+        Ok(())
+    }
+}
+
+fn print_timings(timings: &[(&str, Duration)]) {
+    println!("Phase timings:");
+    for (phase, duration) in timings {
+        println!("  {phase}: {duration:?}");
+    }
+    let total: Duration = timings.iter().map(|(_, duration)| *duration).sum();
+    println!("  Total: {total:?}");
+}
+
+/// Saves and reloads the simulation, and checks the copy is indistinguishable from the original
+fn check_save(sim: &mut Simulation, arena: &mut Arena) -> anyhow::Result<()> {
+    let mut bytes = vec![];
+    sim.save(&mut bytes)?;
+    let mut copy = Simulation::load(bytes.as_slice())?;
+
+    let request = || {
+        let mut request = TickRequest::default();
+        request.view.enabled = true;
+        request
+    };
+    let original_view = sim.tick(request(), arena);
+    let copy_view = copy.tick(request(), arena);
+    arena.reset();
+    if original_view != copy_view {
+        bail!("the reloaded simulation does not produce the same view");
+    }
+
+    let mut copy_bytes = vec![];
+    copy.save(&mut copy_bytes)?;
+    let mut original_bytes = vec![];
+    sim.save(&mut original_bytes)?;
+    if copy_bytes != original_bytes {
+        bail!("the reloaded simulation does not save to the same data");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> anyhow::Result<Options> {
+        Options::parse(args.split_whitespace().map(str::to_string))
+    }
+
+    /// The message of the error the arguments are refused with
+    fn refusal(args: &str) -> String {
+        match parse(args) {
+            Ok(_) => panic!("'{args}' is accepted"),
+            Err(err) => format!("{err:#}"),
+        }
+    }
+
+    #[test]
+    fn options_are_parsed() {
+        let options = parse("--seed 7 --turns 20 --record run.ron --check-save --timings").unwrap();
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.turns, Some(20));
+        assert_eq!(options.record.as_deref(), Some("run.ron"));
+        assert!(options.check_save && options.timings && !options.ai_report);
+        assert!(options.scenario.is_none() && options.load.is_none());
+    }
+
+    #[test]
+    fn malformed_options_are_refused() {
+        assert_eq!(refusal("--fast"), "unknown argument '--fast'");
+        assert_eq!(refusal("--turns"), "missing value for '--turns'");
+        assert!(refusal("--turns many").starts_with("--turns: "));
+    }
+
+    #[test]
+    fn conflicting_options_are_refused() {
+        let exclusive = "--scenario, --load and --replay are mutually exclusive";
+        assert_eq!(refusal("--scenario a.toml --load b.ron"), exclusive);
+        assert_eq!(refusal("--load a.ron --replay b.ron"), exclusive);
+        assert_eq!(refusal("--scenario a.toml --replay b.ron"), exclusive);
+
+        let record = "--record only works on a world created from a scenario";
+        assert_eq!(refusal("--load a.ron --record b.ron"), record);
+        assert_eq!(refusal("--replay a.ron --record b.ron"), record);
+
+        let seed = "--seed only works on a world created from a scenario";
+        assert_eq!(refusal("--load a.ron --seed 3"), seed);
+        assert_eq!(refusal("--replay a.ron --seed 3"), seed);
+
+        assert!(parse("--scenario a.toml --seed 3 --record b.ron").is_ok());
+    }
+}
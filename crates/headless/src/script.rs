@@ -0,0 +1,143 @@
+use anyhow::{Context, bail};
+
+/// A list of commands to perform at given turns. Each line of a script reads
+/// `<turn> <command> [arguments]`, and blank lines and `#` comments are ignored.
+///
+/// Commands:
+/// - `select <tag>`: selects the entity with the given tag
+/// - `pick <list> <n>`: selects the n-th (1-based) entry of a list shown for the selected
+///   entity, such as `people_here`
+/// - `activate`: makes the selected entity the active agent
+/// - `action <name>`: performs the available action with the given name
+pub(crate) struct Script {
+    pub lines: Vec<Line>,
+}
+
+pub(crate) struct Line {
+    pub number: usize,
+    pub turn: usize,
+    pub command: Command,
+}
+
+pub(crate) enum Command {
+    Select(String),
+    Pick(String, usize),
+    Activate,
+    Action(String),
+}
+
+impl Script {
+    pub fn parse(source: &str) -> anyhow::Result<Script> {
+        let mut lines = vec![];
+        for (idx, text) in source.lines().enumerate() {
+            let number = idx + 1;
+            let text = text.split('#').next().unwrap_or_default().trim();
+            if text.is_empty() {
+                continue;
+            }
+            let line = parse_line(text).with_context(|| format!("script line {number}"))?;
+            lines.push(Line {
+                number,
+                turn: line.0,
+                command: line.1,
+            });
+        }
+        Ok(Script { lines })
+    }
+
+    pub fn at_turn(&self, turn: usize) -> impl Iterator<Item = &Line> {
+        self.lines.iter().filter(move |line| line.turn == turn)
+    }
+}
+
+fn parse_line(text: &str) -> anyhow::Result<(usize, Command)> {
+    let (turn, rest) = text.split_once(' ').unwrap_or((text, ""));
+    let turn = turn
+        .parse()
+        .with_context(|| format!("invalid turn '{turn}'"))?;
+
+    let rest = rest.trim();
+    let (command, args) = rest.split_once(' ').unwrap_or((rest, ""));
+    let args = args.trim();
+
+    let command = match command {
+        "select" if !args.is_empty() => Command::Select(args.to_string()),
+        "pick" => {
+            let Some((list, n)) = args.split_once(' ') else {
+                bail!("usage: pick <list> <n>");
+            };
+            let n = n
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid index '{n}'"))?;
+            Command::Pick(list.to_string(), n)
+        }
+        "activate" => Command::Activate,
+        "action" if !args.is_empty() => Command::Action(args.to_string()),
+        "select" | "action" => bail!("'{command}' needs an argument"),
+        _ => bail!("unknown command '{command}'"),
+    };
+    Ok((turn, command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The message of the error the text is refused with, with its context
+    fn refusal(text: &str) -> String {
+        match parse_line(text) {
+            Ok(_) => panic!("'{text}' is accepted"),
+            Err(err) => format!("{err:#}"),
+        }
+    }
+
+    #[test]
+    fn valid_lines_are_parsed() {
+        assert!(matches!(
+            parse_line("3 select caer_ligualid"),
+            Ok((3, Command::Select(tag))) if tag == "caer_ligualid"
+        ));
+        assert!(matches!(
+            parse_line("0 pick people_here  2"),
+            Ok((0, Command::Pick(list, 2))) if list == "people_here"
+        ));
+        assert!(matches!(
+            parse_line("12 activate"),
+            Ok((12, Command::Activate))
+        ));
+        assert!(matches!(
+            parse_line("5 action Send Grain"),
+            Ok((5, Command::Action(name))) if name == "Send Grain"
+        ));
+    }
+
+    #[test]
+    fn malformed_lines_are_refused() {
+        assert_eq!(
+            refusal("soon activate"),
+            "invalid turn 'soon': invalid digit found in string"
+        );
+        assert_eq!(refusal("1 select"), "'select' needs an argument");
+        assert_eq!(refusal("1 action"), "'action' needs an argument");
+        assert_eq!(refusal("1 pick people_here"), "usage: pick <list> <n>");
+        assert!(refusal("1 pick people_here first").starts_with("invalid index 'first'"));
+        assert_eq!(refusal("1 dance"), "unknown command 'dance'");
+    }
+
+    #[test]
+    fn blank_lines_and_comments_are_skipped() {
+        let script =
+            Script::parse("# a comment\n\n2 activate # trailing\n\n4 select anava\n").unwrap();
+        let lines: Vec<_> = script
+            .lines
+            .iter()
+            .map(|line| (line.number, line.turn))
+            .collect();
+        assert_eq!(lines, [(3, 2), (5, 4)]);
+        assert_eq!(script.at_turn(4).count(), 1);
+
+        let err = Script::parse("1 activate\n2 dance\n").err().unwrap();
+        assert_eq!(format!("{err:#}"), "script line 2: unknown command 'dance'");
+    }
+}
@@ -0,0 +1,528 @@
+use rand::rngs::SmallRng;
+use serde::{Deserialize, Serialize};
+use slotmap::Key;
+use util::arena::Arena;
+use util::intern::intern;
+use util::tally::Tally;
+
+use crate::aspects::AspectId;
+use crate::diplomacy;
+use crate::economy::Good;
+use crate::entities::*;
+use crate::events::EventKind;
+use crate::movement;
+use crate::query::{self, Query};
+use crate::simulation::{Action, Simulation};
+use crate::spawn::{self, PrototypeArgs};
+use crate::vassals;
+
+/// An entry of the action catalog: something one entity, the subject, can do towards another,
+/// the target. The same catalog serves the player and the AI alike
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ActionDef {
+    pub tag: String,
+    pub name: String,
+    pub subject: Requirement,
+    pub target: Requirement,
+    /// How the target must stand to the subject in each hierarchy
+    pub relations: Vec<(HierarchyName, Kinship)>,
+    /// Most steps along the site graph between the subject and the target, if any
+    pub max_distance: Option<usize>,
+    /// Taken from the stockpile of the place the subject is at
+    pub cost: Vec<(Good, f64)>,
+    pub effects: Vec<Effect>,
+}
+
+/// What an entity must be to take part in an action
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Requirement {
+    pub flags: Vec<Flag>,
+    pub kind: Option<String>,
+    pub travels: bool,
+    /// Least amount of each aspect
+    pub aspects: Vec<(AspectId, f64)>,
+}
+
+/// How the target of an action stands to its subject in a hierarchy
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Kinship {
+    /// The target is the direct parent of the subject
+    Parent,
+    NotParent,
+    /// The target is anywhere above the subject
+    Ancestor,
+    NotAncestor,
+    /// Both are under the same root
+    SameRoot,
+    OtherRoot,
+}
+
+/// Which side of an action an effect applies to
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Role {
+    Subject,
+    Target,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) enum Effect {
+    /// Spawns an entity of the prototype at the place of one side, for the faction of the
+    /// subject
+    Spawn { prototype: String, at: Role },
+    SetParent {
+        hierarchy: HierarchyName,
+        child: Role,
+        parent: Role,
+    },
+    /// Sends the subject towards the site of the target
+    Travel,
+    /// Adds goods to, or takes them from, the stockpile of the place of one side
+    Stockpile { of: Role, good: Good, amount: f64 },
+    /// Changes the opinion the realms of the two sides have of each other
+    Relation { amount: f64 },
+}
+
+/// Everything the subject can do towards the target right now: the entries of the catalog,
+/// and the diplomacy and realignments of the realm and faction each of them belongs to, for
+/// subjects who rule those
+pub(crate) fn options(sim: &Simulation, subject: EntityId, target: EntityId) -> Vec<Action> {
+    let mut out = vec![];
+    if spawn::is_despawning(sim, subject) || spawn::is_despawning(sim, target) {
+        return out;
+    }
+    for idx in available(sim, subject, target) {
+        out.push(Action {
+            name: intern(&sim.actions[idx].name),
+            catalog: Some((idx, subject, target)),
+            ..Default::default()
+        });
+    }
+
+    let from = diplomacy::realm_of(sim, subject);
+    let to = diplomacy::realm_of(sim, target);
+    let actions = if rules(sim, subject, from) {
+        diplomacy::available_actions(sim, from, to)
+    } else {
+        vec![]
+    };
+    for action in actions {
+        out.push(Action {
+            name: action.name(),
+            diplomacy: Some((action, from, to)),
+            ..Default::default()
+        });
+    }
+
+    let from = vassals::faction_of(sim, subject);
+    let to = vassals::faction_of(sim, target);
+    let realignments = if rules(sim, subject, from) {
+        vassals::available_realignments(sim, from, to)
+    } else {
+        vec![]
+    };
+    for realignment in realignments {
+        out.push(Action {
+            name: realignment.name(),
+            realignment: Some((realignment, from, to)),
+            ..Default::default()
+        });
+    }
+    out
+}
+
+/// Whether the person is the ruler of the faction
+pub(crate) fn rules(sim: &Simulation, person: EntityId, faction: EntityId) -> bool {
+    !faction.is_null() && sim.entities[faction].links.get(LinkName::Ruler) == person
+}
+
+impl Action {
+    pub fn perform(&self, sim: &mut Simulation, arena: &Arena, rng: &mut SmallRng) {
+        if let Some((idx, subject, target)) = self.catalog {
+            perform(sim, arena, rng, idx, subject, target);
+        }
+        if let Some((action, from, to)) = self.diplomacy {
+            action.perform(sim, from, to);
+        }
+        if let Some((realignment, from, to)) = self.realignment {
+            realignment.perform(sim, from, to);
+        }
+    }
+}
+
+/// The entries of the catalog the subject can take towards the target right now
+pub(crate) fn available(sim: &Simulation, subject: EntityId, target: EntityId) -> Vec<usize> {
+    sim.actions
+        .iter()
+        .enumerate()
+        .filter(|(_, def)| is_available(sim, def, subject, target))
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// Whether every requirement of the action is met, every cost can be paid and every effect
+/// can take place
+pub(crate) fn is_available(
+    sim: &Simulation,
+    def: &ActionDef,
+    subject: EntityId,
+    target: EntityId,
+) -> bool {
+    if subject.is_null() || target.is_null() || subject == target {
+        return false;
+    }
+    let entities = &sim.entities;
+    let mut targets = def.target.query(sim);
+    if let Some(max) = def.max_distance {
+        targets = targets.within(query::site_of(sim, subject), max);
+    }
+    if !def.subject.query(sim).matches(&entities[subject]) || !targets.matches(&entities[target]) {
+        return false;
+    }
+
+    let stands = def
+        .relations
+        .iter()
+        .all(|&(rel, kinship)| kinship.holds(entities, rel, subject, target));
+    if !stands {
+        return false;
+    }
+
+    // The cost and the effects may draw on the same stockpile, so what they do to it is
+    // weighed together
+    let mut changes = Tally::new();
+    let home = home_of(sim, subject);
+    for &(good, amount) in &def.cost {
+        changes.add_one((home, good), -amount);
+    }
+    for effect in &def.effects {
+        if let &Effect::Stockpile { of, good, amount } = effect {
+            changes.add_one((home_of(sim, of.pick(subject, target)), good), amount);
+        }
+    }
+    let affordable = changes.iter().all(|((home, good), change)| {
+        entities[home]
+            .economy
+            .as_ref()
+            .is_some_and(|economy| economy.stockpile.get(good) + change >= 0.)
+    });
+    if !affordable {
+        return false;
+    }
+
+    def.effects
+        .iter()
+        .all(|effect| effect.can_apply(sim, subject, target))
+}
+
+/// Has the subject take the action towards the target, paying its cost, unless it is not
+/// available. Returns whether it went through
+pub(crate) fn perform(
+    sim: &mut Simulation,
+    arena: &Arena,
+    rng: &mut SmallRng,
+    action: usize,
+    subject: EntityId,
+    target: EntityId,
+) -> bool {
+    let Some(def) = sim.actions.get(action).cloned() else {
+        return false;
+    };
+    if !is_available(sim, &def, subject, target) {
+        return false;
+    }
+
+    let home = home_of(sim, subject);
+    if let Some(economy) = sim
+        .entities
+        .get_mut(home)
+        .and_then(|entity| entity.economy.as_mut())
+    {
+        for &(good, amount) in &def.cost {
+            economy.stockpile.modify(good, |stock| stock - amount);
+        }
+    }
+
+    for effect in &def.effects {
+        effect.apply(sim, arena, rng, subject, target);
+    }
+    true
+}
+
+impl Requirement {
+    /// Searches for the entities that meet the requirement
+    fn query<'a>(&'a self, sim: &'a Simulation) -> Query<'a> {
+        let mut query = Query::new(sim).with_flags(&self.flags);
+        if let Some(kind) = &self.kind {
+            query = query.of_kind(kind);
+        }
+        if self.travels {
+            query = query.mobile();
+        }
+        for &(aspect, at_least) in &self.aspects {
+            query = query.with_aspect(aspect, at_least);
+        }
+        query
+    }
+}
+
+impl Kinship {
+    fn holds(
+        self,
+        entities: &Entities,
+        rel: HierarchyName,
+        subject: EntityId,
+        target: EntityId,
+    ) -> bool {
+        let is_parent = entities[subject].hierarchies.parent(rel) == target;
+        let is_ancestor = entities.is_descendant(rel, subject, target);
+        let same_root = entities.root_of(rel, subject) == entities.root_of(rel, target);
+        match self {
+            Self::Parent => is_parent,
+            Self::NotParent => !is_parent,
+            Self::Ancestor => is_ancestor,
+            Self::NotAncestor => !is_ancestor,
+            Self::SameRoot => same_root,
+            Self::OtherRoot => !same_root,
+        }
+    }
+}
+
+impl Role {
+    pub fn pick(self, subject: EntityId, target: EntityId) -> EntityId {
+        match self {
+            Self::Subject => subject,
+            Self::Target => target,
+        }
+    }
+}
+
+impl Effect {
+    fn can_apply(&self, sim: &Simulation, subject: EntityId, target: EntityId) -> bool {
+        match self {
+            Self::Spawn { prototype, at } => {
+                let Some(proto) = sim.prototypes.lookup(prototype) else {
+                    return false;
+                };
+                let location = place_of(sim, at.pick(subject, target));
+                let faction = vassals::faction_of(sim, subject);
+                (!proto.has_location || !location.is_null())
+                    && (!proto.has_faction || !faction.is_null())
+            }
+            Self::SetParent {
+                hierarchy,
+                child,
+                parent,
+            } => {
+                let child = child.pick(subject, target);
+                let parent = parent.pick(subject, target);
+                child != parent
+                    && sim.entities[child].hierarchies.parent(*hierarchy) != parent
+                    && !sim.entities.is_descendant(*hierarchy, parent, child)
+            }
+            Self::Travel => {
+                let site = query::site_of(sim, target);
+                sim.entities[subject]
+                    .movement
+                    .as_ref()
+                    .is_some_and(|movement| !site.is_null() && site != movement.destination())
+            }
+            Self::Stockpile { of, good, amount } => {
+                let home = home_of(sim, of.pick(subject, target));
+                sim.entities[home]
+                    .economy
+                    .as_ref()
+                    .is_some_and(|economy| economy.stockpile.get(*good) + amount >= 0.)
+            }
+            Self::Relation { .. } => {
+                let from = diplomacy::realm_of(sim, subject);
+                let to = diplomacy::realm_of(sim, target);
+                !from.is_null() && !to.is_null() && from != to
+            }
+        }
+    }
+
+    fn apply(
+        &self,
+        sim: &mut Simulation,
+        arena: &Arena,
+        rng: &mut SmallRng,
+        subject: EntityId,
+        target: EntityId,
+    ) {
+        match self {
+            Self::Spawn { prototype, at } => {
+                let Some(proto) = sim.prototypes.lookup(prototype).cloned() else {
+                    return;
+                };
+                let args = PrototypeArgs {
+                    location: place_of(sim, at.pick(subject, target)),
+                    faction: vassals::faction_of(sim, subject),
+                    ..Default::default()
+                };
+                let spawned = proto.spawn(sim, arena, rng, &args);
+
+                let mut text = format!("{} recruited {}", sim.entities[subject].name, proto.name);
+                if !args.location.is_null() {
+                    text += &format!(" at {}", sim.entities[args.location].name);
+                }
+                sim.record_event(EventKind::Recruited, text, vec![spawned, subject]);
+            }
+            Self::SetParent {
+                hierarchy,
+                child,
+                parent,
+            } => {
+                let child = child.pick(subject, target);
+                let parent = parent.pick(subject, target);
+                if sim.entities.set_parent(*hierarchy, child, parent).is_err() {
+                    return;
+                }
+                let text = format!(
+                    "{} joined {}",
+                    sim.entities[child].name, sim.entities[parent].name
+                );
+                sim.record_event(EventKind::ParentChanged, text, vec![child, parent]);
+                if *hierarchy == HierarchyName::Faction {
+                    // Vassals have no foreign policy of their own
+                    if sim.entities[child].flags.get(Flag::IsFaction) {
+                        diplomacy::forget(sim, child);
+                    }
+                    vassals::mark_colours_dirty(&mut sim.entities, child);
+                }
+            }
+            Self::Travel => {
+                let site = query::site_of(sim, target);
+                movement::set_destination(sim, subject, site);
+            }
+            Self::Stockpile { of, good, amount } => {
+                let home = home_of(sim, of.pick(subject, target));
+                if let Some(economy) = sim
+                    .entities
+                    .get_mut(home)
+                    .and_then(|entity| entity.economy.as_mut())
+                {
+                    economy.stockpile.modify(*good, |stock| stock + amount);
+                }
+            }
+            Self::Relation { amount } => {
+                let from = diplomacy::realm_of(sim, subject);
+                let to = diplomacy::realm_of(sim, target);
+                sim.diplomacy.change_score(from, to, *amount);
+            }
+        }
+    }
+}
+
+/// The place an entity is at: itself for places, otherwise wherever it is staying
+pub(crate) fn place_of(sim: &Simulation, entity: EntityId) -> EntityId {
+    let data = &sim.entities[entity];
+    if data.flags.get(Flag::IsPlace) {
+        entity
+    } else {
+        data.hierarchies.parent(HierarchyName::PlaceOf)
+    }
+}
+
+/// The entity whose stockpile pays for what an entity does: itself if it keeps one, otherwise
+/// the place it is at
+pub(crate) fn home_of(sim: &Simulation, entity: EntityId) -> EntityId {
+    if sim.entities[entity].economy.is_some() {
+        entity
+    } else {
+        sim.entities[entity]
+            .hierarchies
+            .parent(HierarchyName::PlaceOf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An action a person can take towards the place they are at, doing nothing but the effect
+    fn define(sim: &mut Simulation, cost: Vec<(Good, f64)>, effect: Effect) -> usize {
+        sim.actions.push(ActionDef {
+            tag: "test".to_string(),
+            name: "Test".to_string(),
+            subject: Requirement {
+                flags: vec![Flag::IsPerson],
+                ..Default::default()
+            },
+            target: Requirement {
+                flags: vec![Flag::IsLocation],
+                ..Default::default()
+            },
+            relations: vec![],
+            max_distance: Some(0),
+            cost,
+            effects: vec![effect],
+        });
+        sim.actions.len() - 1
+    }
+
+    fn person_at(sim: &Simulation, place: EntityId) -> EntityId {
+        sim.entities[place]
+            .hierarchies
+            .children(HierarchyName::PlaceOf)
+            .iter()
+            .copied()
+            .find(|&id| sim.entities[id].flags.get(Flag::IsPerson))
+            .unwrap()
+    }
+
+    fn stockpile(sim: &mut Simulation, place: EntityId) -> &mut Tally<Good> {
+        &mut sim.entities[place].economy.as_mut().unwrap().stockpile
+    }
+
+    #[test]
+    fn cost_and_effects_on_the_same_stockpile_are_weighed_together() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let place = sim.entities.lookup("caer_ligualid");
+        let person = person_at(&sim, place);
+        let effect = Effect::Stockpile {
+            of: Role::Subject,
+            good: Good::Grain,
+            amount: -30.,
+        };
+        let action = define(&mut sim, vec![(Good::Grain, 50.)], effect);
+
+        // Enough for the cost or for the effect, but not for both
+        stockpile(&mut sim, place).set(Good::Grain, 60.);
+        let def = sim.actions[action].clone();
+        assert!(!is_available(&sim, &def, person, place));
+
+        stockpile(&mut sim, place).set(Good::Grain, 80.);
+        assert!(is_available(&sim, &def, person, place));
+        let mut rng = sim.rngs.stream(crate::rng::RngStream::Interaction);
+        assert!(perform(&mut sim, &arena, &mut rng, action, person, place));
+        assert_eq!(stockpile(&mut sim, place).get(Good::Grain), 0.);
+    }
+
+    #[test]
+    fn nothing_is_made_its_own_parent() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let place = sim.entities.lookup("caer_ligualid");
+        let person = person_at(&sim, place);
+        let effect = Effect::SetParent {
+            hierarchy: HierarchyName::Faction,
+            child: Role::Subject,
+            parent: Role::Subject,
+        };
+        let action = define(&mut sim, vec![], effect);
+
+        let def = sim.actions[action].clone();
+        assert!(!is_available(&sim, &def, person, place));
+        let mut rng = sim.rngs.stream(crate::rng::RngStream::Interaction);
+        assert!(!perform(&mut sim, &arena, &mut rng, action, person, place));
+        assert_ne!(
+            sim.entities[person]
+                .hierarchies
+                .parent(HierarchyName::Faction),
+            person
+        );
+    }
+}
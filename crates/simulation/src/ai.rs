@@ -0,0 +1,411 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use rand::Rng;
+use rand::rngs::SmallRng;
+use slotmap::Key;
+use util::arena::Arena;
+
+use crate::actions::{self, Effect};
+use crate::aspects;
+use crate::diplomacy::{self, DiplomaticAction, DiplomaticState, MAX_SCORE};
+use crate::entities::*;
+use crate::query::Query;
+use crate::simulation::{Action, Simulation};
+use crate::spawn;
+use crate::vassals::{self, Realignment};
+
+/// Score an option needs to beat for an agent to bother with it
+const IDLE_SCORE: f64 = 0.1;
+/// Most that chance adds to the score of an option, so that agents in the same spot do not
+/// all act alike
+const NOISE: f64 = 0.05;
+/// Worth of a new entity in an empty place; each entity already there divides it further
+const SPAWN_VALUE: f64 = 0.4;
+/// Worth of goods that cover all of a shortfall
+const NEED_VALUE: f64 = 1.;
+/// How much spending a whole stockpile weighs against an option
+const COST_WEIGHT: f64 = 0.5;
+/// Worth of a change of opinion by the full range, towards realms one is on bad terms with
+const RECONCILIATION_VALUE: f64 = 2.;
+/// How far better suited another faction must be before a person changes sides
+const LOYALTY: f64 = 0.3;
+/// How bitter relations must be before a ruler goes to war, all else being even
+const WAR_RELUCTANCE: f64 = 0.5;
+/// How much the balance of strength sways a ruler towards war
+const STRENGTH_WEIGHT: f64 = 0.3;
+/// How much more willing to make peace rulers get with each turn of war
+const WAR_WEARINESS: f64 = 0.03;
+/// How unlike its liege a vassal must be before it breaks away, all else being even
+const INDEPENDENCE_RELUCTANCE: f64 = 0.75;
+/// How many times stronger an enemy must be before a faction gives in and swears fealty
+const SURRENDER_RATIO: f64 = 3.;
+/// Turns of war a faction holds out for before it considers giving in
+const SURRENDER_DELAY: usize = 8;
+/// Worth of annexing a vassal left without a ruler
+const ANNEX_VALUE: f64 = 0.5;
+
+/// What an agent weighed up on its last turn, kept for debugging
+pub(crate) struct Deliberation {
+    pub agent: EntityId,
+    /// Every option with its target and score, best first
+    pub options: Vec<(&'static str, EntityId, f64)>,
+    /// Whether the best option was taken, rather than doing nothing
+    pub acted: bool,
+}
+
+/// Has every person other than the player's agent weigh up what they can do, and do whatever
+/// seems best, if anything. Only rulers act for their faction, and never for the player's
+pub(crate) fn take_turns(sim: &mut Simulation, arena: &Arena, rng: &mut SmallRng) {
+    let agents: Vec<EntityId> = sim
+        .entities
+        .with_flag(Flag::IsPerson)
+        .iter()
+        .copied()
+        .filter(|&id| id != sim.active_agent)
+        .collect();
+    sim.deliberations.clear();
+
+    for agent in agents {
+        // Earlier agents may have done away with later ones
+        if spawn::is_despawning(sim, agent) {
+            continue;
+        }
+        let mut scored = deliberate(sim, arena, agent, rng);
+        scored.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+
+        let acted = scored
+            .first()
+            .is_some_and(|(_, _, score)| *score > IDLE_SCORE);
+        if acted {
+            scored[0].0.perform(sim, arena, rng);
+        }
+        sim.deliberations.push(Deliberation {
+            agent,
+            options: scored
+                .into_iter()
+                .map(|(action, target, score)| (action.name, target, score))
+                .collect(),
+            acted,
+        });
+    }
+}
+
+/// Every option the agent has towards the locations and factions of the world, as the player
+/// would see them, with its score
+fn deliberate(
+    sim: &Simulation,
+    arena: &Arena,
+    agent: EntityId,
+    rng: &mut SmallRng,
+) -> Vec<(Action, EntityId, f64)> {
+    let targets = Query::new(sim)
+        .with_any_flag(&[Flag::IsLocation, Flag::IsFaction])
+        .run(arena);
+
+    // Diplomacy and realignments are the same whichever entity of the other side is targeted
+    let mut seen = BTreeSet::new();
+    let mut out = vec![];
+    for target in targets {
+        for action in actions::options(sim, agent, target.id) {
+            let parties = action
+                .diplomacy
+                .map(|(_, from, to)| (from, to))
+                .or(action.realignment.map(|(_, from, to)| (from, to)));
+            if let Some(parties) = parties
+                && !seen.insert((action.name, parties))
+            {
+                continue;
+            }
+            if let Some(score) = score(sim, agent, &action) {
+                out.push((action, target.id, score + rng.gen_range(0.0..NOISE)));
+            }
+        }
+    }
+    out
+}
+
+/// How worthwhile the option looks to the agent, or nothing if it is not theirs to take
+fn score(sim: &Simulation, agent: EntityId, action: &Action) -> Option<f64> {
+    if let Some((idx, subject, target)) = action.catalog {
+        return score_catalog(sim, idx, subject, target);
+    }
+    if let Some((action, from, to)) = action.diplomacy {
+        if !speaks_for(sim, agent, from) {
+            return None;
+        }
+        let relation = sim.diplomacy.get(from, to);
+        let opinion = relation.score / MAX_SCORE;
+        let value = match action {
+            DiplomaticAction::DeclareWar => {
+                let balance = (strength(sim, from) / strength(sim, to).max(1.) - 1.).clamp(-1., 1.);
+                -opinion - WAR_RELUCTANCE + balance * STRENGTH_WEIGHT
+            }
+            DiplomaticAction::OfferPeace => {
+                let DiplomaticState::War { since } = relation.state else {
+                    return None;
+                };
+                opinion + WAR_WEARINESS * (sim.turn_number - since) as f64
+            }
+            DiplomaticAction::ProposeAlliance => opinion,
+        };
+        return Some(value);
+    }
+    if let Some((realignment, from, to)) = action.realignment {
+        if !speaks_for(sim, agent, from) {
+            return None;
+        }
+        let value = match realignment {
+            Realignment::DeclareIndependence => {
+                let similarity = aspects::profile(&sim.entities, from)
+                    .cosine_similarity(&aspects::profile(&sim.entities, to));
+                let realm = diplomacy::realm_of(sim, from);
+                let balance =
+                    (strength(sim, from) / strength(sim, realm).max(1.) - 1.).clamp(-1., 1.);
+                1. - similarity - INDEPENDENCE_RELUCTANCE + balance * STRENGTH_WEIGHT
+            }
+            Realignment::SwearFealty => {
+                // Only ever to the realm one is losing a war against
+                let ours = diplomacy::realm_of(sim, from);
+                let DiplomaticState::War { since } = sim.diplomacy.get(ours, to).state else {
+                    return None;
+                };
+                if sim.turn_number - since < SURRENDER_DELAY {
+                    return None;
+                }
+                (strength(sim, to) / strength(sim, ours).max(1.) - SURRENDER_RATIO) * 0.2
+            }
+            Realignment::Annex => {
+                if !sim.entities[to].links.get(LinkName::Ruler).is_null() {
+                    return None;
+                }
+                ANNEX_VALUE
+            }
+        };
+        return Some(value);
+    }
+    None
+}
+
+fn score_catalog(sim: &Simulation, idx: usize, subject: EntityId, target: EntityId) -> Option<f64> {
+    let def = &sim.actions[idx];
+    let mut value = 0.;
+
+    for effect in &def.effects {
+        value += match effect {
+            Effect::Spawn { at, .. } => {
+                let place = actions::place_of(sim, at.pick(subject, target));
+                let crowd = sim.entities[place]
+                    .hierarchies
+                    .children(HierarchyName::PlaceOf)
+                    .len();
+                SPAWN_VALUE / (1 + crowd) as f64
+            }
+            // Travelling is only worth it for what can be done on arrival, which agents do not
+            // plan for
+            Effect::Travel => 0.,
+            &Effect::SetParent {
+                hierarchy: HierarchyName::Faction,
+                child,
+                parent,
+            } => {
+                let child = child.pick(subject, target);
+                let parent = parent.pick(subject, target);
+                if child != subject || rules_any(sim, subject) {
+                    return None;
+                }
+                let current = sim.entities[subject]
+                    .hierarchies
+                    .parent(HierarchyName::Faction);
+                let own = &sim.entities[subject].aspects;
+                let fit =
+                    |faction| own.cosine_similarity(&aspects::profile(&sim.entities, faction));
+                fit(parent) - fit(current) - LOYALTY
+            }
+            Effect::SetParent { .. } => 0.,
+            &Effect::Stockpile { of, good, amount } => {
+                let home = actions::home_of(sim, of.pick(subject, target));
+                let shortfall = sim.entities[home]
+                    .economy
+                    .as_ref()
+                    .map(|economy| economy.shortfall.get(good))
+                    .unwrap_or_default();
+                if amount > 0. {
+                    NEED_VALUE * shortfall.min(amount) / amount
+                } else {
+                    0.
+                }
+            }
+            &Effect::Relation { amount } => {
+                let from = diplomacy::realm_of(sim, subject);
+                let to = diplomacy::realm_of(sim, target);
+                if !speaks_for(sim, subject, from) {
+                    return None;
+                }
+                let weight = if sim.diplomacy.get(from, to).score < 0. {
+                    RECONCILIATION_VALUE
+                } else {
+                    RECONCILIATION_VALUE / 4.
+                };
+                weight * amount / MAX_SCORE
+            }
+        };
+    }
+
+    let home = actions::home_of(sim, subject);
+    if let Some(economy) = sim.entities[home].economy.as_ref() {
+        for &(good, amount) in &def.cost {
+            value -= COST_WEIGHT * amount / economy.stockpile.get(good).max(amount);
+        }
+    }
+    Some(value)
+}
+
+/// Whether the agent rules the faction, which is not one the player acts for
+fn speaks_for(sim: &Simulation, agent: EntityId, faction: EntityId) -> bool {
+    let player = vassals::faction_of(sim, sim.active_agent);
+    actions::rules(sim, agent, faction)
+        && faction != player
+        && faction != diplomacy::realm_of(sim, player)
+}
+
+fn rules_any(sim: &Simulation, person: EntityId) -> bool {
+    Query::new(sim).linked_to(LinkName::Ruler, person).any()
+}
+
+/// Number of locations held by the faction and its vassals
+fn strength(sim: &Simulation, faction: EntityId) -> f64 {
+    Query::new(sim)
+        .with_flags(&[Flag::IsLocation])
+        .below(HierarchyName::Faction, faction)
+        .count() as f64
+}
+
+/// Lists what every agent weighed up on the last turn, best option first, marking the one
+/// taken
+pub(crate) fn report(sim: &Simulation) -> String {
+    let mut out = String::new();
+    let name = |id: EntityId| sim.entities.get(id).map_or("(gone)", |e| e.name.as_str());
+    for deliberation in &sim.deliberations {
+        let _ = writeln!(out, "{}:", name(deliberation.agent));
+        for (idx, &(action, target, score)) in deliberation.options.iter().enumerate() {
+            let mark = if idx == 0 && deliberation.acted {
+                '*'
+            } else {
+                ' '
+            };
+            let _ = writeln!(out, "  {mark} {score:>6.3} {action} -> {}", name(target));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ruler(sim: &Simulation, faction: EntityId) -> EntityId {
+        sim.entities[faction].links.get(LinkName::Ruler)
+    }
+
+    fn economy(sim: &mut Simulation, id: EntityId) -> &mut crate::economy::Economy {
+        sim.entities[id].economy.as_mut().unwrap()
+    }
+
+    fn diplomacy(action: DiplomaticAction, from: EntityId, to: EntityId) -> Action {
+        Action {
+            diplomacy: Some((action, from, to)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn goods_are_worth_more_where_they_are_short() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let town = sim.entities.lookup("caer_ligualid");
+        let anava = sim.entities.lookup("anava");
+        let idx = sim
+            .actions
+            .iter()
+            .position(|x| x.tag == "send_grain")
+            .unwrap();
+        let person = sim.entities[town]
+            .hierarchies
+            .children(HierarchyName::PlaceOf)[0];
+        let grain = crate::economy::Good::Grain;
+        economy(&mut sim, town).stockpile.set(grain, 1000.);
+        economy(&mut sim, anava).shortfall = Default::default();
+        let idle = score_catalog(&sim, idx, person, anava).unwrap();
+        economy(&mut sim, anava).shortfall.set(grain, 25.);
+        let short = score_catalog(&sim, idx, person, anava).unwrap();
+        economy(&mut sim, anava).shortfall.set(grain, 500.);
+        let starving = score_catalog(&sim, idx, person, anava).unwrap();
+
+        assert!(idle < IDLE_SCORE);
+        assert!(idle < short && short < starving);
+        // Sending more than the whole amount is worth no more than covering it
+        assert!((starving - idle - NEED_VALUE).abs() < 1e-9);
+    }
+
+    #[test]
+    fn only_rulers_of_factions_the_player_does_not_hold_speak_for_them() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let rheged = sim.entities.lookup("rheged");
+        let bernicia = sim.entities.lookup("bernicia");
+        let war = diplomacy(DiplomaticAction::DeclareWar, rheged, bernicia);
+
+        assert!(score(&sim, ruler(&sim, rheged), &war).is_some());
+        assert!(score(&sim, ruler(&sim, bernicia), &war).is_none());
+
+        // Nor do they for the realm of the player
+        let drust = sim.entities.lookup("clan_drust");
+        sim.active_agent = ruler(&sim, drust);
+        assert!(score(&sim, ruler(&sim, rheged), &war).is_none());
+    }
+
+    #[test]
+    fn bad_blood_and_strength_lead_to_war_and_weariness_to_peace() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let rheged = sim.entities.lookup("rheged");
+        let bernicia = sim.entities.lookup("bernicia");
+        let agent = ruler(&sim, rheged);
+        let war = diplomacy(DiplomaticAction::DeclareWar, rheged, bernicia);
+
+        sim.diplomacy.change_score(rheged, bernicia, 50.);
+        let friendly = score(&sim, agent, &war).unwrap();
+        sim.diplomacy.change_score(rheged, bernicia, -100.);
+        let hostile = score(&sim, agent, &war).unwrap();
+        assert!(friendly < hostile);
+
+        // Rheged holds more locations than Bernicia, so it is the one keener on war
+        let theirs = diplomacy(DiplomaticAction::DeclareWar, bernicia, rheged);
+        assert!(score(&sim, ruler(&sim, bernicia), &theirs).unwrap() < hostile);
+
+        DiplomaticAction::DeclareWar.perform(&mut sim, rheged, bernicia);
+        let peace = diplomacy(DiplomaticAction::OfferPeace, rheged, bernicia);
+        let early = score(&sim, agent, &peace).unwrap();
+        sim.turn_number += 20;
+        let late = score(&sim, agent, &peace).unwrap();
+        assert!((late - early - 20. * WAR_WEARINESS).abs() < 1e-9);
+    }
+
+    #[test]
+    fn only_vassals_without_a_ruler_are_annexed() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let rheged = sim.entities.lookup("rheged");
+        let drust = sim.entities.lookup("clan_drust");
+        let annex = Action {
+            realignment: Some((Realignment::Annex, rheged, drust)),
+            ..Default::default()
+        };
+
+        assert_eq!(score(&sim, ruler(&sim, rheged), &annex), None);
+        sim.entities
+            .set_link(drust, LinkName::Ruler, EntityId::null());
+        assert_eq!(score(&sim, ruler(&sim, rheged), &annex), Some(ANNEX_VALUE));
+    }
+}
@@ -1,4 +1,8 @@
-#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+use serde::{Deserialize, Serialize};
+
+use crate::entities::*;
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub(crate) struct AspectId(pub usize);
 
 pub(crate) struct AspectData {
@@ -53,7 +57,7 @@ impl Aspects {
         let mut out = AspectVector::new(self);
         for &(tag, amt) in tags {
             let id = self.lookup(tag).id;
-            if id != AspectId::default() {
+            if id == AspectId::default() {
                 continue;
             }
             out.set(id, amt);
@@ -87,4 +91,181 @@ impl AspectVector {
             *x = value
         }
     }
+
+    /// Like `set`, but first makes room for the aspect if the vector is too short to hold it
+    pub fn set_growing(&mut self, id: AspectId, value: f64) {
+        if self.0.len() <= id.0 {
+            self.0.resize(id.0 + 1, 0.);
+        }
+        self.0[id.0] = value;
+    }
+
+    pub fn as_slice(&self) -> &[f64] {
+        &self.0
+    }
+
+    pub fn from_vec(values: Vec<f64>) -> Self {
+        Self(values)
+    }
+
+    /// Non-zero entries, skipping the null aspect
+    pub fn iter(&self) -> impl Iterator<Item = (AspectId, f64)> + '_ {
+        self.0
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter(|(_, value)| **value != 0.)
+            .map(|(idx, value)| (AspectId(idx), *value))
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.0.iter().fold(0., |acc, x| acc + x)
+    }
+
+    pub fn add(&mut self, other: &AspectVector) {
+        self.add_scaled(other, 1.);
+    }
+
+    pub fn add_scaled(&mut self, other: &AspectVector, factor: f64) {
+        if self.0.len() < other.0.len() {
+            self.0.resize(other.0.len(), 0.);
+        }
+        for (x, y) in self.0.iter_mut().zip(&other.0) {
+            *x += y * factor;
+        }
+    }
+
+    pub fn scale(&mut self, factor: f64) {
+        for x in &mut self.0 {
+            *x *= factor;
+        }
+    }
+
+    /// Scales the vector so that its entries add up to one. A vector that adds up to zero is
+    /// left as it is
+    pub fn normalize(&mut self) {
+        let sum = self.sum();
+        if sum != 0. {
+            self.scale(1. / sum);
+        }
+    }
+
+    pub fn dot(&self, other: &AspectVector) -> f64 {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .fold(0., |acc, (x, y)| acc + x * y)
+    }
+
+    /// How alike the two vectors are regardless of their magnitude, from zero (nothing in
+    /// common) to one (same proportions). Zero vectors are alike to nothing
+    pub fn cosine_similarity(&self, other: &AspectVector) -> f64 {
+        let norms = (self.dot(self) * other.dot(other)).sqrt();
+        if norms == 0. {
+            return 0.;
+        }
+        self.dot(other) / norms
+    }
+
+    /// Moves the vector a fraction `t` of the way towards the other one
+    pub fn blend(&self, other: &AspectVector, t: f64) -> AspectVector {
+        let mut out = self.clone();
+        out.scale(1. - t);
+        out.add_scaled(other, t);
+        out
+    }
+}
+
+/// Adds up the aspects of an entity with those of its children in a hierarchy, recursively.
+/// The aspects of an entity weigh as much as they add up to, and a population weighs as much
+/// as its headcount, so the result is a mass to be normalized before showing it as shares
+pub(crate) fn aggregate(entities: &Entities, entity: EntityId, rel: HierarchyName) -> AspectVector {
+    let data = &entities[entity];
+    let mut out = data.aspects.clone();
+
+    if let Some(population) = &data.population {
+        for (culture, heads) in population.by_culture.iter() {
+            out.set_growing(culture, out.get(culture) + heads);
+        }
+    }
+
+    for &child in data.hierarchies.children(rel) {
+        out.add(&aggregate(entities, child, rel));
+    }
+    out
+}
+
+/// The aspect profile of an entity: its people for places, its members for factions, and its
+/// own aspects otherwise
+pub(crate) fn profile(entities: &Entities, entity: EntityId) -> AspectVector {
+    let flags = &entities[entity].flags;
+    let mut out = if flags.get(Flag::IsFaction) {
+        aggregate(entities, entity, HierarchyName::Faction)
+    } else if flags.get(Flag::IsPlace) {
+        aggregate(entities, entity, HierarchyName::PlaceOf)
+    } else {
+        entities[entity].aspects.clone()
+    };
+    out.normalize();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aspects() -> Aspects {
+        let mut aspects = Aspects::new();
+        aspects.define("anglish", "Anglish");
+        aspects.define("brythonic", "Brythonic");
+        aspects
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn parsing_skips_unknown_tags() {
+        let aspects = aspects();
+        let vector = aspects.parse_vector(&[("brythonic", 2.), ("pictish", 5.)]);
+        assert_eq!(vector.as_slice(), &[0., 0., 2.]);
+        let entries: Vec<_> = vector.iter().collect();
+        assert!(entries == [(aspects.lookup("brythonic").id, 2.)]);
+    }
+
+    #[test]
+    fn normalizing_makes_shares() {
+        let mut vector = AspectVector::from_vec(vec![0., 1., 3.]);
+        vector.normalize();
+        assert_eq!(vector.as_slice(), &[0., 0.25, 0.75]);
+
+        let mut zero = AspectVector::from_vec(vec![0., 0., 0.]);
+        zero.normalize();
+        assert_eq!(zero.as_slice(), &[0., 0., 0.]);
+    }
+
+    #[test]
+    fn cosine_similarity_ignores_magnitude() {
+        let a = AspectVector::from_vec(vec![0., 1., 2.]);
+        let b = AspectVector::from_vec(vec![0., 3., 6.]);
+        let c = AspectVector::from_vec(vec![0., 2., -1.]);
+        let zero = AspectVector::from_vec(vec![0., 0., 0.]);
+        assert!(close(a.cosine_similarity(&b), 1.));
+        assert!(close(a.cosine_similarity(&c), 0.));
+        assert_eq!(a.cosine_similarity(&zero), 0.);
+    }
+
+    #[test]
+    fn adding_grows_to_fit_and_blending_interpolates() {
+        let mut short = AspectVector::from_vec(vec![0., 1.]);
+        short.add_scaled(&AspectVector::from_vec(vec![0., 1., 4.]), 0.5);
+        assert_eq!(short.as_slice(), &[0., 1.5, 2.]);
+        assert_eq!(short.sum(), 3.5);
+
+        let from = AspectVector::from_vec(vec![0., 4., 0.]);
+        let to = AspectVector::from_vec(vec![0., 0., 8.]);
+        assert_eq!(from.blend(&to, 0.25).as_slice(), &[0., 3., 2.]);
+        assert_eq!(from.dot(&to), 0.);
+    }
 }
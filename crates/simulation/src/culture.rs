@@ -0,0 +1,201 @@
+use std::collections::VecDeque;
+
+use rand::rngs::SmallRng;
+use slotmap::{Key, SecondaryMap};
+use util::arena::Arena;
+use util::tally::Tally;
+
+use crate::aspects::{self, AspectId, AspectVector};
+use crate::entities::*;
+use crate::events::EventKind;
+use crate::simulation::Simulation;
+use crate::sites::SiteId;
+
+/// Share of the way a location's cultural make-up moves towards its surroundings each turn
+const DRIFT_RATE: f64 = 0.02;
+/// How much the ruling faction weighs in the surroundings of a location, against its neighbours
+const FACTION_WEIGHT: f64 = 0.5;
+/// Share of the people a culture needs for a location to take it up as its own
+const FLIP_THRESHOLD: f64 = 0.6;
+
+/// Has the people of every location take after their neighbours and rulers, and switches the
+/// culture of locations where another culture has come to dominate
+pub(crate) fn drift(sim: &mut Simulation, _: &Arena, _: &mut SmallRng) {
+    let locations: Vec<EntityId> = sim
+        .entities
+        .iter()
+        .filter(|entity| entity.population.is_some())
+        .map(|entity| entity.id)
+        .collect();
+
+    // Work out every new make-up from the old ones before changing any of them
+    let targets: Vec<(EntityId, AspectVector)> = locations
+        .iter()
+        .map(|&id| (id, surroundings(sim, id)))
+        .collect();
+
+    for (id, target) in targets {
+        if target.sum() == 0. {
+            continue;
+        }
+        let population = sim.entities[id].population.as_mut().unwrap();
+        let total = population.total();
+        let current = shares(&population.by_culture);
+        let next = current.blend(&target, DRIFT_RATE);
+
+        let mut by_culture = Tally::new();
+        for (culture, share) in next.iter() {
+            by_culture.set(culture, share * total);
+        }
+        population.by_culture = by_culture;
+
+        flip_culture(sim, id, &next);
+    }
+}
+
+/// The cultural make-up a location is pulled towards: that of the locations next to it,
+/// weighted by how many people they have, blended with that of the faction ruling it
+fn surroundings(sim: &Simulation, location: EntityId) -> AspectVector {
+    let mut neighbours = AspectVector::default();
+    for neighbour in neighbouring_locations(sim, location) {
+        if let Some(population) = &sim.entities[neighbour].population {
+            neighbours.add_scaled(&shares(&population.by_culture), population.total());
+        }
+    }
+    neighbours.normalize();
+
+    let faction = sim.entities[location]
+        .hierarchies
+        .parent(HierarchyName::Faction);
+    if faction.is_null() {
+        return neighbours;
+    }
+    let rulers = cultural(sim, &aspects::profile(&sim.entities, faction));
+    if neighbours.sum() == 0. {
+        return rulers;
+    }
+    neighbours.blend(&rulers, FACTION_WEIGHT)
+}
+
+/// Keeps only the aspects that stand for a culture, as shares adding up to one
+fn cultural(sim: &Simulation, profile: &AspectVector) -> AspectVector {
+    let mut out = AspectVector::default();
+    for (aspect, value) in profile.iter() {
+        let culture = sim.entities.lookup(&sim.aspects[aspect].tag);
+        if sim.entities[culture].kind_name == "Culture" {
+            out.set_growing(aspect, value);
+        }
+    }
+    out.normalize();
+    out
+}
+
+fn shares(by_culture: &Tally<AspectId>) -> AspectVector {
+    let mut out = AspectVector::default();
+    for (culture, heads) in by_culture.iter() {
+        out.set_growing(culture, heads);
+    }
+    out.normalize();
+    out
+}
+
+/// The locations that can be reached from this one without going through another location
+pub(crate) fn neighbouring_locations(sim: &Simulation, location: EntityId) -> Vec<EntityId> {
+    let start = sim.entities[location].bound_site;
+    let mut out = vec![];
+    let mut visited: SecondaryMap<SiteId, ()> = SecondaryMap::new();
+    let mut queue = VecDeque::from([start]);
+    visited.insert(start, ());
+
+    while let Some(site) = queue.pop_front() {
+        for neighbour in sim.sites.graph.neighbours(site) {
+            if visited.insert(neighbour.id, ()).is_some() {
+                continue;
+            }
+            let bound = sim.sites.data[neighbour.id].bound_entity;
+            if bound.is_null() {
+                queue.push_back(neighbour.id);
+            } else if sim.entities[bound].population.is_some() {
+                out.push(bound);
+            }
+        }
+    }
+    out
+}
+
+fn flip_culture(sim: &mut Simulation, location: EntityId, shares: &AspectVector) {
+    let Some((dominant, share)) = shares.iter().max_by(|a, b| a.1.total_cmp(&b.1)) else {
+        return;
+    };
+    if share < FLIP_THRESHOLD {
+        return;
+    }
+
+    let culture = sim.entities.lookup(&sim.aspects[dominant].tag);
+    let previous = sim.entities[location].links.get(LinkName::Culture);
+    if culture.is_null() || culture == previous {
+        return;
+    }
+
+    sim.entities.set_link(location, LinkName::Culture, culture);
+
+    let mut text = format!(
+        "{} has become {}",
+        sim.entities[location].name, sim.entities[culture].name
+    );
+    let mut subjects = vec![location, culture];
+    if !previous.is_null() {
+        text += &format!(", leaving behind its {} ways", sim.entities[previous].name);
+        subjects.push(previous);
+    }
+    sim.record_event(EventKind::CultureChanged, text, subjects);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations_take_up_a_culture_once_it_dominates() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let anava = sim.entities.lookup("anava");
+        let anglish = sim.entities.lookup("anglish");
+        let brythonic = sim.entities.lookup("brythonic");
+        let aspect = sim.aspects.lookup("anglish").id;
+
+        let mut shares = AspectVector::new(&sim.aspects);
+        shares.set(aspect, FLIP_THRESHOLD - 0.1);
+        flip_culture(&mut sim, anava, &shares);
+        assert_eq!(sim.entities[anava].links.get(LinkName::Culture), brythonic);
+
+        shares.set(aspect, FLIP_THRESHOLD + 0.1);
+        flip_culture(&mut sim, anava, &shares);
+        assert_eq!(sim.entities[anava].links.get(LinkName::Culture), anglish);
+        let event = sim.events.iter().last().unwrap();
+        assert_eq!(event.kind, EventKind::CultureChanged);
+        assert_eq!(event.subjects, [anava, anglish, brythonic]);
+
+        // Staying dominant is no change
+        let events = sim.events.iter().count();
+        flip_culture(&mut sim, anava, &shares);
+        assert_eq!(sim.events.iter().count(), events);
+    }
+
+    #[test]
+    fn people_take_after_their_surroundings() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let mut rng = sim.rngs.stream(crate::rng::RngStream::Interaction);
+        let anava = sim.entities.lookup("anava");
+        let aspect = sim.aspects.lookup("anglish").id;
+        let population = |sim: &Simulation| sim.entities[anava].population.clone().unwrap();
+
+        // Anava is mostly Brythonic, among Brythonic neighbours, under a Brythonic realm
+        let before = population(&sim);
+        drift(&mut sim, &arena, &mut rng);
+        let after = population(&sim);
+        assert!((after.total() - before.total()).abs() < 1e-6);
+        assert!(after.by_culture.get(aspect) < before.by_culture.get(aspect));
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::BTreeMap;
+
+use rand::rngs::SmallRng;
+use serde::{Deserialize, Serialize};
+use slotmap::Key;
+use util::arena::Arena;
+
+use crate::aspects;
+use crate::entities::*;
+use crate::events::EventKind;
+use crate::simulation::Simulation;
+
+/// Opinions range from -`MAX_SCORE` (hatred) to `MAX_SCORE` (friendship)
+pub(crate) const MAX_SCORE: f64 = 100.;
+/// Share of the way opinions move towards where circumstances would put them each turn
+const SCORE_DRIFT: f64 = 0.1;
+/// Opinion lost by a realm towards one that declares war on it
+const WAR_DECLARATION_PENALTY: f64 = 50.;
+/// How long a truce lasts, in turns
+const TRUCE_LENGTH: usize = 10;
+/// How much more willing to make peace a realm gets with each turn of war
+const WAR_WEARINESS: f64 = 5.;
+/// Opinion two realms need to have of each other to become allies
+const ALLIANCE_THRESHOLD: f64 = 40.;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub(crate) enum DiplomaticState {
+    #[default]
+    Peace,
+    War {
+        since: usize,
+    },
+    /// No war may be declared until the given turn
+    Truce {
+        until: usize,
+    },
+    Alliance,
+}
+
+impl DiplomaticState {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Peace => "Peace",
+            Self::War { .. } => "War",
+            Self::Truce { .. } => "Truce",
+            Self::Alliance => "Alliance",
+        }
+    }
+}
+
+/// How two realms stand with each other
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Relation {
+    pub score: f64,
+    pub state: DiplomaticState,
+}
+
+/// Relations between independent realms, that is, factions at the root of the `Faction`
+/// hierarchy. Vassals have no foreign policy of their own and share that of their realm
+#[derive(Default)]
+pub(crate) struct Diplomacy {
+    /// Keyed by the pair of realms, the lesser id first. Pairs that are missing are at peace
+    /// and indifferent to each other
+    relations: BTreeMap<(EntityId, EntityId), Relation>,
+}
+
+fn key(a: EntityId, b: EntityId) -> (EntityId, EntityId) {
+    (a.min(b), a.max(b))
+}
+
+impl Diplomacy {
+    pub fn get(&self, a: EntityId, b: EntityId) -> Relation {
+        self.relations.get(&key(a, b)).copied().unwrap_or_default()
+    }
+
+    pub fn set(&mut self, a: EntityId, b: EntityId, relation: Relation) {
+        self.relations.insert(key(a, b), relation);
+    }
+
+    /// Moves the opinion two realms have of each other by the amount, within bounds
+    pub fn change_score(&mut self, a: EntityId, b: EntityId, by: f64) {
+        let relation = self.relations.entry(key(a, b)).or_default();
+        relation.score = (relation.score + by).clamp(-MAX_SCORE, MAX_SCORE);
+    }
+
+    /// Drops every relation of a realm that no longer stands on its own
+    pub fn forget(&mut self, realm: EntityId) {
+        self.relations.retain(|&(a, b), _| a != realm && b != realm);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, EntityId, Relation)> + '_ {
+        self.relations
+            .iter()
+            .map(|(&(a, b), &relation)| (a, b, relation))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum DiplomaticAction {
+    DeclareWar,
+    OfferPeace,
+    ProposeAlliance,
+}
+
+/// Drops every relation and alliance of a realm that no longer stands on its own
+pub(crate) fn forget(sim: &mut Simulation, realm: EntityId) {
+    sim.diplomacy.forget(realm);
+    let allies: Vec<EntityId> = sim.entities[realm].links.all(LinkName::Ally).collect();
+    for ally in allies {
+        sim.entities.remove_link(realm, LinkName::Ally, ally);
+        sim.entities.remove_link(ally, LinkName::Ally, realm);
+    }
+}
+
+/// The realm an entity belongs to
+pub(crate) fn realm_of(sim: &Simulation, entity: EntityId) -> EntityId {
+    let realm = sim.entities.root_of(HierarchyName::Faction, entity);
+    if sim.entities[realm].flags.get(Flag::IsFaction) {
+        realm
+    } else {
+        EntityId::null()
+    }
+}
+
+/// Every independent realm, in id order
+pub(crate) fn realms(sim: &Simulation) -> Vec<EntityId> {
+    sim.entities
+        .with_flag(Flag::IsFaction)
+        .iter()
+        .copied()
+        .filter(|&id| {
+            sim.entities[id]
+                .hierarchies
+                .parent(HierarchyName::Faction)
+                .is_null()
+        })
+        .collect()
+}
+
+/// The diplomatic actions one realm can take towards another
+pub(crate) fn available_actions(
+    sim: &Simulation,
+    from: EntityId,
+    to: EntityId,
+) -> Vec<DiplomaticAction> {
+    let mut out = vec![];
+    if from.is_null() || to.is_null() || from == to {
+        return out;
+    }
+    let relation = sim.diplomacy.get(from, to);
+    match relation.state {
+        DiplomaticState::Peace => {
+            out.push(DiplomaticAction::DeclareWar);
+            if relation.score >= ALLIANCE_THRESHOLD {
+                out.push(DiplomaticAction::ProposeAlliance);
+            }
+        }
+        DiplomaticState::Alliance => out.push(DiplomaticAction::DeclareWar),
+        DiplomaticState::War { .. } => out.push(DiplomaticAction::OfferPeace),
+        DiplomaticState::Truce { .. } => {}
+    }
+    out
+}
+
+impl DiplomaticAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DeclareWar => "Declare War",
+            Self::OfferPeace => "Offer Peace",
+            Self::ProposeAlliance => "Propose Alliance",
+        }
+    }
+
+    /// Carries out the action of one realm towards another. Offers may be turned down, which
+    /// is recorded as an event like the rest
+    pub fn perform(self, sim: &mut Simulation, from: EntityId, to: EntityId) {
+        let mut relation = sim.diplomacy.get(from, to);
+        let turn = sim.turn_number;
+
+        let (kind, verb) = match self {
+            Self::DeclareWar => {
+                sim.entities.remove_link(from, LinkName::Ally, to);
+                sim.entities.remove_link(to, LinkName::Ally, from);
+                relation.state = DiplomaticState::War { since: turn };
+                relation.score = (relation.score - WAR_DECLARATION_PENALTY).max(-MAX_SCORE);
+                (EventKind::WarDeclared, "declared war on")
+            }
+            Self::OfferPeace => {
+                let DiplomaticState::War { since } = relation.state else {
+                    return;
+                };
+                let willingness = relation.score + WAR_WEARINESS * (turn - since) as f64;
+                if willingness >= 0. {
+                    relation.state = DiplomaticState::Truce {
+                        until: turn + TRUCE_LENGTH,
+                    };
+                    (EventKind::PeaceMade, "made peace with")
+                } else {
+                    (EventKind::OfferRefused, "was refused peace by")
+                }
+            }
+            Self::ProposeAlliance => {
+                if relation.score >= ALLIANCE_THRESHOLD {
+                    relation.state = DiplomaticState::Alliance;
+                    sim.entities.add_link(from, LinkName::Ally, to);
+                    sim.entities.add_link(to, LinkName::Ally, from);
+                    (EventKind::AllianceFormed, "allied with")
+                } else {
+                    (EventKind::OfferRefused, "was refused an alliance by")
+                }
+            }
+        };
+
+        sim.diplomacy.set(from, to, relation);
+        let text = format!(
+            "{} {verb} {}",
+            sim.entities[from].name, sim.entities[to].name
+        );
+        sim.record_event(kind, text, vec![from, to]);
+    }
+}
+
+/// Ends truces that ran out, and has opinions between realms move with their circumstances:
+/// realms warm up to those whose people are like theirs and to their allies, and sour on
+/// those they are at war with
+pub(crate) fn update(sim: &mut Simulation, _: &Arena, _: &mut SmallRng) {
+    let realms = realms(sim);
+    let profiles: Vec<_> = realms
+        .iter()
+        .map(|&realm| aspects::profile(&sim.entities, realm))
+        .collect();
+
+    for (i, &a) in realms.iter().enumerate() {
+        for (j, &b) in realms.iter().enumerate().skip(i + 1) {
+            let mut relation = sim.diplomacy.get(a, b);
+
+            let similarity = profiles[i].cosine_similarity(&profiles[j]);
+            let mut target = MAX_SCORE * (2. * similarity - 1.) / 2.;
+            match relation.state {
+                DiplomaticState::War { .. } => target -= MAX_SCORE / 2.,
+                DiplomaticState::Alliance => target += MAX_SCORE / 2.,
+                DiplomaticState::Truce { until } if until <= sim.turn_number => {
+                    relation.state = DiplomaticState::Peace;
+                    let text = format!(
+                        "The truce between {} and {} has ended",
+                        sim.entities[a].name, sim.entities[b].name
+                    );
+                    sim.record_event(EventKind::TruceEnded, text, vec![a, b]);
+                }
+                _ => {}
+            }
+
+            relation.score += (target - relation.score) * SCORE_DRIFT;
+            relation.score = relation.score.clamp(-MAX_SCORE, MAX_SCORE);
+            sim.diplomacy.set(a, b, relation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truces_end_once_their_time_is_up() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let mut rng = sim.rngs.stream(crate::rng::RngStream::Interaction);
+        let rheged = sim.entities.lookup("rheged");
+        let bernicia = sim.entities.lookup("bernicia");
+
+        DiplomaticAction::DeclareWar.perform(&mut sim, rheged, bernicia);
+        sim.turn_number += 20;
+        DiplomaticAction::OfferPeace.perform(&mut sim, rheged, bernicia);
+        let until = sim.turn_number + TRUCE_LENGTH;
+        assert_eq!(
+            sim.diplomacy.get(rheged, bernicia).state,
+            DiplomaticState::Truce { until }
+        );
+
+        // No war can be declared while the truce holds
+        while sim.turn_number < until {
+            assert!(available_actions(&sim, rheged, bernicia).is_empty());
+            update(&mut sim, &arena, &mut rng);
+            assert_eq!(
+                sim.diplomacy.get(bernicia, rheged).state,
+                DiplomaticState::Truce { until }
+            );
+            sim.turn_number += 1;
+        }
+
+        update(&mut sim, &arena, &mut rng);
+        assert_eq!(
+            sim.diplomacy.get(rheged, bernicia).state,
+            DiplomaticState::Peace
+        );
+        let ended = sim.events.iter().last().unwrap();
+        assert_eq!(ended.kind, EventKind::TruceEnded);
+        assert_eq!(ended.subjects, [rheged.min(bernicia), rheged.max(bernicia)]);
+        assert!(matches!(
+            available_actions(&sim, bernicia, rheged).as_slice(),
+            [DiplomaticAction::DeclareWar, ..]
+        ));
+    }
+}
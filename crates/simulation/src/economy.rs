@@ -0,0 +1,136 @@
+use rand::rngs::SmallRng;
+use serde::{Deserialize, Serialize};
+use strum::{EnumCount, EnumIter, EnumString, IntoEnumIterator};
+use util::arena::Arena;
+use util::tally::Tally;
+
+use crate::simulation::Simulation;
+
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Debug,
+    EnumIter,
+    EnumCount,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum Good {
+    Grain,
+    Cattle,
+    Silver,
+}
+
+impl Good {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Grain => "Grain",
+            Self::Cattle => "Cattle",
+            Self::Silver => "Silver",
+        }
+    }
+}
+
+/// Goods held by a location, and the rates at which its commoners make and use them up
+#[derive(Default, Clone)]
+pub(crate) struct Economy {
+    pub stockpile: Tally<Good>,
+    /// Made each turn, per head
+    pub production: Tally<Good>,
+    /// Used up each turn, per head
+    pub consumption: Tally<Good>,
+    /// Net change of the stockpile over the last turn
+    pub flow: Tally<Good>,
+    /// Consumption the stockpile could not cover over the last turn
+    pub shortfall: Tally<Good>,
+}
+
+impl Economy {
+    fn update(&mut self, heads: f64) {
+        self.flow = Tally::new();
+        self.shortfall = Tally::new();
+
+        for good in Good::iter() {
+            let made = self.production.get(good) * heads;
+            let demand = self.consumption.get(good) * heads;
+            if made == 0. && demand == 0. {
+                continue;
+            }
+            let stock = self.stockpile.get(good);
+            let available = stock + made;
+            let left = (available - demand).max(0.);
+
+            self.flow.set(good, left - stock);
+            self.shortfall.set(good, (demand - available).max(0.));
+            self.stockpile.set(good, left);
+        }
+    }
+}
+
+/// Has every location produce and consume goods for the turn
+pub(crate) fn production(sim: &mut Simulation, _: &Arena, _: &mut SmallRng) {
+    let updates: Vec<_> = sim
+        .entities
+        .iter()
+        .filter(|entity| entity.economy.is_some())
+        .map(|entity| {
+            let heads = entity.population.as_ref().map(|p| p.total());
+            (entity.id, heads.unwrap_or_default())
+        })
+        .collect();
+
+    for (id, heads) in updates {
+        if let Some(economy) = sim.entities[id].economy.as_mut() {
+            economy.update(heads);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn economy(stock: f64, production: f64, consumption: f64) -> Economy {
+        let mut economy = Economy::default();
+        economy.stockpile.set(Good::Grain, stock);
+        economy.production.set(Good::Grain, production);
+        economy.consumption.set(Good::Grain, consumption);
+        economy
+    }
+
+    #[test]
+    fn surplus_goes_to_the_stockpile() {
+        let mut economy = economy(10., 2., 1.);
+        economy.update(5.);
+        assert_eq!(economy.stockpile.get(Good::Grain), 15.);
+        assert_eq!(economy.flow.get(Good::Grain), 5.);
+        assert_eq!(economy.shortfall.get(Good::Grain), 0.);
+    }
+
+    #[test]
+    fn shortfalls_empty_the_stockpile_and_are_recorded() {
+        // The stockpile covers what production does not, until it runs out
+        let mut economy = economy(10., 1., 3.);
+        economy.update(4.);
+        assert_eq!(economy.stockpile.get(Good::Grain), 2.);
+        assert_eq!(economy.flow.get(Good::Grain), -8.);
+        assert_eq!(economy.shortfall.get(Good::Grain), 0.);
+
+        economy.update(4.);
+        assert_eq!(economy.stockpile.get(Good::Grain), 0.);
+        assert_eq!(economy.flow.get(Good::Grain), -2.);
+        assert_eq!(economy.shortfall.get(Good::Grain), 6.);
+
+        // A shortfall is only kept for the turn it happened in
+        economy.update(0.);
+        assert_eq!(economy.shortfall.get(Good::Grain), 0.);
+        assert_eq!(economy.stockpile.get(Good::Grain), 0.);
+    }
+}
@@ -1,7 +1,9 @@
+use std::collections::{BTreeMap, BTreeSet};
+
 use macros::*;
-use rand::rngs::SmallRng;
+use serde::{Deserialize, Serialize};
 use slotmap::*;
-use strum::{EnumCount, EnumIter, IntoEnumIterator};
+use strum::{EnumCount, EnumIter, EnumString, IntoEnumIterator};
 use tinybitset::TinyBitSet;
 use util::arena::*;
 use util::misc::VecExt;
@@ -9,7 +11,13 @@ use util::tagged::*;
 
 use crate::RGB;
 use crate::aspects::AspectVector;
-use crate::sites::SiteId;
+use crate::economy::Economy;
+use crate::life::Life;
+use crate::movement::Movement;
+use crate::names::Markov;
+use crate::population::Population;
+use crate::sites::{SiteId, Sites};
+use crate::succession::SuccessionLaw;
 
 new_key_type! { pub(crate) struct EntityId; }
 
@@ -19,6 +27,7 @@ impl ArenaSafe for EntityId {}
 pub(crate) struct EntityData {
     pub id: EntityId,
     pub name: String,
+    /// Changed through `Entities::set_kind`, which keeps the index by kind up to date
     pub kind_name: &'static str,
     /// Some entities are bound to a given site. This records the site
     /// the said entity is linked to
@@ -30,19 +39,41 @@ pub(crate) struct EntityData {
     pub sprite: &'static str,
     pub size: f32,
     pub color: EntityColor,
-    /// Set of flags
+    /// Set of flags, changed through `Entities::set_flags`, which keeps the index by flag up to
+    /// date
     pub flags: Flags,
+    /// Changed through `Entities::set_link`, which keeps the index of incoming links up to date
     pub links: Links,
     // Name lists (for example, for cultures)
     pub name_lists: Option<Box<NameLists>>,
     // Aspects
     pub aspects: AspectVector,
+    /// Present on entities that can travel along the site graph
+    pub movement: Option<Box<Movement>>,
+    /// Present on locations, which hold and trade in goods
+    pub economy: Option<Box<Economy>>,
+    /// Present on locations, which have commoners living in them
+    pub population: Option<Box<Population>>,
+    /// Present on entities that age and die, such as people
+    pub life: Option<Life>,
+    /// Present on factions, which pick a new ruler by it
+    pub succession: Option<SuccessionLaw>,
 }
 
 #[derive(Default)]
 pub(crate) struct Entities {
     entries: SlotMap<EntityId, EntityData>,
     tags: Tags<EntityId>,
+    /// Slots left behind by despawned entities, in the order they were freed. The slot map
+    /// hands out the last one freed first, which this mirrors so that a save can lay the
+    /// slots out again just as they were
+    freed: Vec<EntityId>,
+    /// Every entity with each flag, in id order
+    by_flag: [Vec<EntityId>; Flag::COUNT],
+    /// Every entity of each kind, in id order
+    by_kind: BTreeMap<&'static str, Vec<EntityId>>,
+    /// Every entity linking to each entity, along with the link, in id order
+    linked_from: BTreeMap<EntityId, Vec<(EntityId, LinkName)>>,
     dummy: EntityData,
 }
 
@@ -57,27 +88,203 @@ impl Entities {
 
     pub(crate) fn spawn_with_tag(&mut self, tag: &str) -> &mut EntityData {
         let id = self.entries.insert(EntityData::default());
+        if let Some(reused) = self.freed.pop() {
+            debug_assert_eq!(slot_of(reused), slot_of(id));
+        }
+        self.set_kind(id, "UNKNOWN_KIND");
+        self.init_entry(id, tag)
+    }
+
+    fn init_entry(&mut self, id: EntityId, tag: &str) -> &mut EntityData {
         let data = &mut self.entries[id];
         data.id = id;
-        data.kind_name = "UNKNOWN_KIND";
 
         if !tag.is_empty() {
-            self.tags.unbind(tag);
+            assert!(
+                self.tags.lookup(tag).is_none(),
+                "tag '{tag}' is already taken"
+            );
             self.tags.insert(tag, data.id);
         }
 
         data
     }
 
+    /// Fills an empty collection with blank entities under the given ids and tags, leaving the
+    /// `freed` slots free to be handed out again in the same order as in the collection the ids
+    /// come from. Returns false, leaving the collection empty, if the ids do not make up such
+    /// a layout, carry versions no slot could have reached, or two of them share a tag
+    pub(crate) fn restore(&mut self, live: &[(EntityId, &str)], freed: &[EntityId]) -> bool {
+        assert!(self.entries.is_empty());
+        let slots = live.len() + freed.len();
+        let mut targets = vec![EntityId::null(); slots];
+        for &id in live.iter().map(|(id, _)| id).chain(freed) {
+            let slot = slot_of(id);
+            if slot == 0
+                || slot > slots
+                || !targets[slot - 1].is_null()
+                || version_of(id).is_multiple_of(2)
+                || version_of(id) > MAX_RESTORED_VERSION
+            {
+                return false;
+            }
+            targets[slot - 1] = id;
+        }
+        let mut tags = BTreeSet::new();
+        if live
+            .iter()
+            .any(|&(_, tag)| !tag.is_empty() && !tags.insert(tag))
+        {
+            return false;
+        }
+
+        // Keys only take on a slot's version after it was emptied and filled again enough times
+        for target in targets {
+            let mut id = self.entries.insert(EntityData::default());
+            while version_of(id) < version_of(target) {
+                self.entries.remove(id);
+                id = self.entries.insert(EntityData::default());
+            }
+            assert_eq!(id, target);
+        }
+
+        for &id in freed {
+            self.entries.remove(id);
+        }
+        self.freed = freed.to_vec();
+
+        for &(id, tag) in live {
+            self.init_entry(id, tag);
+            self.set_kind(id, "UNKNOWN_KIND");
+        }
+        true
+    }
+
+    /// Slots freed by despawning that are yet to be handed out again, oldest first
+    pub(crate) fn freed(&self) -> &[EntityId] {
+        &self.freed
+    }
+
     pub(crate) fn despawn(&mut self, id: EntityId) {
         for hierarchy in HierarchyName::iter() {
             self.remove_all_children(hierarchy, id);
             self.unparent(hierarchy, id);
         }
 
-        if let Some(_) = self.entries.remove(id) {
+        // Nothing may keep pointing at an entity that is gone
+        for (source, link) in self.linked_from(id).to_vec() {
+            self.remove_link(source, link, id);
+        }
+        for (link, target) in self[id].links.iter().collect::<Vec<_>>() {
+            self.remove_link(id, link, target);
+        }
+
+        if let Some(data) = self.entries.remove(id) {
+            for flag in Flag::iter().filter(|&flag| data.flags.get(flag)) {
+                unindex(&mut self.by_flag[flag as usize], id);
+            }
+            if let Some(ids) = self.by_kind.get_mut(data.kind_name) {
+                unindex(ids, id);
+            }
             self.tags.remove(&id);
+            self.freed.push(id);
+        }
+    }
+
+    /// Sets or clears the flags of a live entity
+    pub(crate) fn set_flags(&mut self, id: EntityId, flags: &[Flag], value: bool) {
+        let data = get_or_return!(self.entries.get_mut(id));
+        for &flag in flags {
+            if data.flags.get(flag) == value {
+                continue;
+            }
+            data.flags.set(flag, value);
+            let ids = &mut self.by_flag[flag as usize];
+            if value {
+                ids.sorted_insert(id);
+            } else {
+                unindex(ids, id);
+            }
+        }
+    }
+
+    /// Points a link of a live entity at the target alone, or clears it if the target is null
+    pub(crate) fn set_link(&mut self, id: EntityId, link: LinkName, target: EntityId) {
+        let data = get_or_return!(self.entries.get_mut(id));
+        let previous: Vec<EntityId> = data.links.all(link).collect();
+        for previous in previous.into_iter().filter(|&previous| previous != target) {
+            self.remove_link(id, link, previous);
+        }
+        self.add_link(id, link, target);
+    }
+
+    /// Points a link of a live entity at the target as well as at any it already points at
+    pub(crate) fn add_link(&mut self, id: EntityId, link: LinkName, target: EntityId) {
+        let data = get_or_return!(self.entries.get_mut(id));
+        if target.is_null() || !data.links.insert(link, target) {
+            return;
+        }
+        self.linked_from
+            .entry(target)
+            .or_default()
+            .sorted_insert((id, link));
+    }
+
+    /// Stops a link of a live entity from pointing at the target
+    pub(crate) fn remove_link(&mut self, id: EntityId, link: LinkName, target: EntityId) {
+        let data = get_or_return!(self.entries.get_mut(id));
+        if !data.links.remove(link, target) {
+            return;
+        }
+        if let Some(sources) = self.linked_from.get_mut(&target) {
+            unindex(sources, (id, link));
+            if sources.is_empty() {
+                self.linked_from.remove(&target);
+            }
+        }
+    }
+
+    /// Every entity linking to the target, along with the link, in id order
+    pub(crate) fn linked_from(&self, target: EntityId) -> &[(EntityId, LinkName)] {
+        self.linked_from
+            .get(&target)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Every entity whose link points at the target, in id order
+    pub(crate) fn sources(
+        &self,
+        target: EntityId,
+        link: LinkName,
+    ) -> impl Iterator<Item = EntityId> + '_ {
+        self.linked_from(target)
+            .iter()
+            .filter(move |&&(_, name)| name == link)
+            .map(|&(source, _)| source)
+    }
+
+    /// Changes the kind of a live entity
+    pub(crate) fn set_kind(&mut self, id: EntityId, kind: &'static str) {
+        let data = get_or_return!(self.entries.get_mut(id));
+        let previous = std::mem::replace(&mut data.kind_name, kind);
+        if let Some(ids) = self.by_kind.get_mut(previous) {
+            unindex(ids, id);
         }
+        self.by_kind.entry(kind).or_default().sorted_insert(id);
+    }
+
+    /// Every entity with the flag, in id order
+    pub(crate) fn with_flag(&self, flag: Flag) -> &[EntityId] {
+        &self.by_flag[flag as usize]
+    }
+
+    /// Every entity of the kind, in id order
+    pub(crate) fn of_kind(&self, kind: &str) -> &[EntityId] {
+        self.by_kind
+            .get(kind)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
     }
 
     pub(crate) fn len(&self) -> usize {
@@ -88,24 +295,59 @@ impl Entities {
         self.tags.lookup(tag).unwrap_or_default()
     }
 
+    pub(crate) fn tag_of(&self, id: EntityId) -> Option<&str> {
+        self.tags.reverse_lookup(&id)
+    }
+
     pub(crate) fn iter<'a>(&'a self) -> slotmap::basic::Values<'a, EntityId, EntityData> {
         self.entries.values()
     }
 
+    pub(crate) fn iter_mut<'a>(
+        &'a mut self,
+    ) -> slotmap::basic::ValuesMut<'a, EntityId, EntityData> {
+        self.entries.values_mut()
+    }
+
+    pub(crate) fn get(&self, id: EntityId) -> Option<&EntityData> {
+        self.entries.get(id)
+    }
+
+    /// Whether the id is of an entity that is still around, which is never the case for null
+    pub(crate) fn contains(&self, id: EntityId) -> bool {
+        self.entries.contains_key(id)
+    }
+
     pub(crate) fn get_mut(&mut self, id: EntityId) -> Option<&mut EntityData> {
         self.entries.get_mut(id)
     }
 }
 
+fn unindex<T: Ord>(ids: &mut Vec<T>, id: T) {
+    if let Ok(idx) = ids.binary_search(&id) {
+        ids.remove(idx);
+    }
+}
+
+/// Highest version a restored id may have. Getting a slot to a version takes refilling it once
+/// for every two versions, so the cap keeps a corrupt save from having that go on for ages
+const MAX_RESTORED_VERSION: u32 = 1 << 16;
+
+fn slot_of(id: EntityId) -> usize {
+    id.data().as_ffi() as u32 as usize
+}
+
+fn version_of(id: EntityId) -> u32 {
+    (id.data().as_ffi() >> 32) as u32
+}
+
 impl std::ops::Index<EntityId> for Entities {
     type Output = EntityData;
 
+    /// Null and despawned ids read as a blank entity, so that stale ids held on to from before a
+    /// despawn come out as nothing rather than a crash
     fn index(&self, index: EntityId) -> &Self::Output {
-        if !index.is_null() {
-            &self.entries[index]
-        } else {
-            &self.dummy
-        }
+        self.entries.get(index).unwrap_or(&self.dummy)
     }
 }
 
@@ -126,10 +368,14 @@ impl<'a> TaggedCollection for &'a Entities {
 pub(crate) struct EntityColor {
     pub current: RGB,
     pub dirty: bool,
+    /// Whether the colour is taken from the faction, and so needs refreshing when it changes
+    pub dynamic: bool,
 }
 
 // Parent-child relationships
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumIter, EnumCount)]
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumIter, EnumCount, Serialize, Deserialize,
+)]
 pub(crate) enum HierarchyName {
     /// Links a faction to the location that act as its capital
     Capital,
@@ -137,6 +383,10 @@ pub(crate) enum HierarchyName {
     Faction,
     /// Links a location to the entities therein located
     PlaceOf,
+    /// Links a person to their children, through whom their dynasty carries on
+    Family,
+    /// Links a dynasty to every person born into it
+    Dynasty,
 }
 
 impl ArenaSafe for HierarchyName {}
@@ -187,6 +437,23 @@ impl Entities {
         this
     }
 
+    /// Whether the entity sits anywhere below the ancestor in the hierarchy
+    pub(crate) fn is_descendant(
+        &self,
+        rel: HierarchyName,
+        entity: EntityId,
+        ancestor: EntityId,
+    ) -> bool {
+        let mut this = self[entity].hierarchies.parent(rel);
+        while !this.is_null() {
+            if this == ancestor {
+                return true;
+            }
+            this = self[this].hierarchies.parent(rel);
+        }
+        false
+    }
+
     pub(crate) fn ancestry<'a>(
         &self,
         arena: &'a Arena,
@@ -208,33 +475,48 @@ impl Entities {
         out.into_bump_slice()
     }
 
-    pub(crate) fn set_parent(&mut self, rel: HierarchyName, child: EntityId, parent: EntityId) {
-        // We can't operate on a null child
-        if child.is_null() {
-            return;
+    /// Moves the child under the parent. Detaching an entity from its parent is done through
+    /// `unparent` instead, so both have to be live entities, and the parent may not be the
+    /// child itself or sit anywhere below it
+    pub(crate) fn set_parent(
+        &mut self,
+        rel: HierarchyName,
+        child: EntityId,
+        parent: EntityId,
+    ) -> Result<(), HierarchyError> {
+        if !self.entries.contains_key(child) {
+            return Err(HierarchyError::MissingChild);
+        }
+        if !self.entries.contains_key(parent) {
+            return Err(HierarchyError::MissingParent);
+        }
+        if child == parent || self.is_descendant(rel, parent, child) {
+            return Err(HierarchyError::Cycle);
         }
+
         // Remove current parent
         self.unparent(rel, child);
 
         // Add new parent
         let idx = rel as usize;
-        let child_data = self.get_mut(child).unwrap();
-        child_data.hierarchies.0[idx].parent = parent;
-
-        // If parent is self or is a null, then stop here -- nothing to do
-        if child == parent || parent.is_null() {
-            return;
-        }
-
-        // Otherwise get the parent and update its child list
-        let parent = self.get_mut(parent).unwrap();
-        let children = &mut parent.hierarchies.0[idx].children;
+        self.entries[child].hierarchies.0[idx].parent = parent;
+        let children = &mut self.entries[parent].hierarchies.0[idx].children;
         children.sorted_insert(child);
+        Ok(())
     }
 
-    pub(crate) fn make_sibling(&mut self, rel: HierarchyName, child: EntityId, sibling: EntityId) {
-        let parent = self.entries[sibling].hierarchies.parent(rel);
-        self.set_parent(rel, child, parent);
+    pub(crate) fn make_sibling(
+        &mut self,
+        rel: HierarchyName,
+        child: EntityId,
+        sibling: EntityId,
+    ) -> Result<(), HierarchyError> {
+        let parent = self[sibling].hierarchies.parent(rel);
+        if parent.is_null() {
+            self.unparent(rel, child);
+            return Ok(());
+        }
+        self.set_parent(rel, child, parent)
     }
 
     pub(crate) fn unparent(&mut self, rel: HierarchyName, child: EntityId) {
@@ -295,11 +577,154 @@ impl Entities {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, EnumIter, EnumCount)]
+impl Entities {
+    /// Checks that the entities agree with each other and with the sites: every parent lists
+    /// its children in order and each of them points back at it, no entity is its own
+    /// ancestor, and entities and sites bound together point at each other
+    pub(crate) fn audit(&self, sites: &Sites) -> Result<(), String> {
+        let name = |id: EntityId| format!("'{}' ({id:?})", self[id].name);
+
+        for entity in self.iter() {
+            for rel in HierarchyName::iter() {
+                let parent = entity.hierarchies.parent(rel);
+                if !parent.is_null() {
+                    if !self.entries.contains_key(parent) {
+                        return Err(format!("{} has a despawned parent", name(entity.id)));
+                    }
+                    if !self[parent].hierarchies.children(rel).contains(&entity.id) {
+                        return Err(format!(
+                            "{} is missing from the children of its parent {}",
+                            name(entity.id),
+                            name(parent)
+                        ));
+                    }
+                }
+
+                let children = entity.hierarchies.children(rel);
+                if !children.is_sorted_by(|a, b| a < b) {
+                    return Err(format!("children of {} are out of order", name(entity.id)));
+                }
+                for &child in children {
+                    if !self.entries.contains_key(child)
+                        || self[child].hierarchies.parent(rel) != entity.id
+                    {
+                        return Err(format!(
+                            "{} lists {child:?} as a child, which does not point back at it",
+                            name(entity.id)
+                        ));
+                    }
+                }
+
+                // An ancestry longer than there are entities has to go round in circles
+                let mut this = parent;
+                for _ in 0..self.len() {
+                    if this.is_null() {
+                        break;
+                    }
+                    this = self[this].hierarchies.parent(rel);
+                }
+                if !this.is_null() {
+                    return Err(format!("{} is its own ancestor", name(entity.id)));
+                }
+            }
+
+            for (link, target) in entity.links.iter() {
+                if !self.entries.contains_key(target) {
+                    return Err(format!("{} links to a despawned entity", name(entity.id)));
+                }
+                if self
+                    .linked_from(target)
+                    .binary_search(&(entity.id, link))
+                    .is_err()
+                {
+                    return Err(format!(
+                        "{} is missing from the incoming links of {}",
+                        name(entity.id),
+                        name(target)
+                    ));
+                }
+            }
+            for &(source, link) in self.linked_from(entity.id) {
+                if !self
+                    .get(source)
+                    .is_some_and(|data| data.links.all(link).any(|target| target == entity.id))
+                {
+                    return Err(format!(
+                        "{} lists {source:?} as linking to it, which does not",
+                        name(entity.id)
+                    ));
+                }
+            }
+
+            let site = entity.bound_site;
+            if !site.is_null()
+                && sites.data.get(site).map(|data| data.bound_entity) != Some(entity.id)
+            {
+                return Err(format!(
+                    "{} is bound to a site that is not bound to it",
+                    name(entity.id)
+                ));
+            }
+        }
+
+        for site in sites.data.values() {
+            let entity = site.bound_entity;
+            if !entity.is_null()
+                && self.entries.get(entity).map(|data| data.bound_site) != Some(site.id)
+            {
+                return Err(format!(
+                    "site '{}' is bound to an entity that is not bound to it",
+                    sites.tags.reverse_lookup(&site.id).unwrap_or_default()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Why a change to a hierarchy was turned down
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum HierarchyError {
+    /// The child is null or was despawned
+    MissingChild,
+    /// The parent is null or was despawned
+    MissingParent,
+    /// The parent is the child itself or sits below it
+    Cycle,
+}
+
+impl std::fmt::Display for HierarchyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingChild => write!(f, "the child does not exist"),
+            Self::MissingParent => write!(f, "the parent does not exist"),
+            Self::Cycle => write!(f, "the entity would become its own ancestor"),
+        }
+    }
+}
+
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Debug,
+    EnumIter,
+    EnumCount,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
 pub(crate) enum Flag {
     IsCard,
+    IsDynasty,
     IsFaction,
     IsLocation,
+    IsParty,
     IsPerson,
     IsPlace,
 }
@@ -310,7 +735,7 @@ pub(crate) struct Flags(TinyBitSet<u8, FLAG_BACKING_SIZE>);
 
 impl Flags {
     #[inline]
-    pub fn set(&mut self, flag: Flag, value: bool) {
+    fn set(&mut self, flag: Flag, value: bool) {
         self.0.assign(flag as usize, value);
     }
 
@@ -319,62 +744,292 @@ impl Flags {
         self.0[flag as usize]
     }
 
-    #[inline]
-    pub fn set_all(&mut self, flags: &[Flag], value: bool) {
-        for &flag in flags {
-            self.set(flag, value);
-        }
-    }
-
     #[inline]
     pub fn check_all(&self, flags: &[Flag]) -> bool {
         flags.iter().all(|flag| self.get(*flag))
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, EnumIter, EnumCount)]
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Debug,
+    EnumIter,
+    EnumCount,
+    Serialize,
+    Deserialize,
+)]
 pub(crate) enum LinkName {
     Culture,
+    /// Between two people married to each other, both ways
+    Spouse,
+    /// Links a person to the parent they do not take their dynasty from, the other one being
+    /// their parent in the `Family` hierarchy
+    SecondParent,
+    /// Links a faction to the person ruling it, the reverse of which are the titles a person
+    /// holds
+    Ruler,
+    /// Links a person or location to the faith it keeps
+    Religion,
+    /// Between two allied realms, both ways. A realm may have any number of allies
+    Ally,
 }
 
+/// Where each link of an entity points, sorted by link. Most links point at one entity at most,
+/// but some may point at several
 #[derive(Default)]
-pub(crate) struct Links([EntityId; LinkName::COUNT]);
+pub(crate) struct Links(Vec<(LinkName, EntityId)>);
 
 impl Links {
+    /// Where the link points, or the first entity it points at if there are several
     #[inline]
     pub fn get(&self, link: LinkName) -> EntityId {
-        self.0[link as usize]
+        self.all(link).next().unwrap_or_default()
     }
 
-    #[inline]
-    pub fn set(&mut self, link: LinkName, entity: EntityId) {
-        self.0[link as usize] = entity;
+    /// Every entity the link points at, in id order
+    pub fn all(&self, link: LinkName) -> impl Iterator<Item = EntityId> + '_ {
+        let start = self.0.partition_point(|&(name, _)| name < link);
+        self.0[start..]
+            .iter()
+            .take_while(move |&&(name, _)| name == link)
+            .map(|&(_, target)| target)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LinkName, EntityId)> + '_ {
+        self.0.iter().copied()
+    }
+
+    /// Returns false if the link already pointed at the target
+    fn insert(&mut self, link: LinkName, target: EntityId) -> bool {
+        match self.0.binary_search(&(link, target)) {
+            Ok(_) => false,
+            Err(idx) => {
+                self.0.insert(idx, (link, target));
+                true
+            }
+        }
+    }
+
+    /// Returns false if the link did not point at the target
+    fn remove(&mut self, link: LinkName, target: EntityId) -> bool {
+        match self.0.binary_search(&(link, target)) {
+            Ok(idx) => {
+                self.0.remove(idx);
+                true
+            }
+            Err(_) => false,
+        }
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, EnumIter, EnumCount)]
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Debug,
+    EnumIter,
+    EnumCount,
+    Serialize,
+    Deserialize,
+)]
 pub(crate) enum NameList {
-    PersonalNames,
+    MaleNames,
+    FemaleNames,
+    /// Told apart from others of the same name by these, such as "the Tall"
+    Epithets,
+    PlaceNames,
 }
 
+/// How a culture names its people and places, which `names` makes up new names after
 #[derive(Default)]
-pub(crate) struct NameLists(pub [Vec<String>; NameList::COUNT]);
+pub(crate) struct NameLists {
+    lists: [Vec<String>; NameList::COUNT],
+    /// Trained on each list as it is set, to make up names after it
+    chains: [Markov; NameList::COUNT],
+    /// How sons are named after their father, with `{}` standing in for his name, as in
+    /// "ap {}". Those of cultures without one go by their given name alone
+    pub son_of: Option<String>,
+    pub daughter_of: Option<String>,
+}
 
 impl NameLists {
     #[inline]
     pub fn get(&self, list: NameList) -> &[String] {
-        &self.0[list as usize]
+        &self.lists[list as usize]
     }
 
     #[inline]
+    pub fn chain(&self, list: NameList) -> &Markov {
+        &self.chains[list as usize]
+    }
+
     pub fn with(mut self, list: NameList, value: Vec<String>) -> Self {
-        self.0[list as usize] = value;
+        self.chains[list as usize] = Markov::train(&value);
+        self.lists[list as usize] = value;
         self
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(entities: &mut Entities) -> EntityId {
+        entities.spawn_with_tag("").id
+    }
+
+    fn key(slot: u32, version: u32) -> EntityId {
+        KeyData::from_ffi(((version as u64) << 32) | slot as u64).into()
+    }
+
+    #[test]
+    fn restore_reuses_ids_and_frees_slots() {
+        let mut entities = Entities::default();
+        assert!(entities.restore(&[(key(1, 1), "a"), (key(3, 5), "")], &[key(2, 3)]));
+        assert_eq!(entities.lookup("a"), key(1, 1));
+        assert!(entities.contains(key(3, 5)));
+        assert!(!entities.contains(key(2, 3)));
+    }
+
+    #[test]
+    fn restore_refuses_versions_out_of_range() {
+        for version in [MAX_RESTORED_VERSION + 1, u32::MAX] {
+            let mut entities = Entities::default();
+            assert!(!entities.restore(&[(key(1, version), "")], &[]));
+        }
+    }
+
+    #[test]
+    fn set_parent_refuses_cycles() {
+        let mut entities = Entities::default();
+        let [a, b, c] = [(); 3].map(|_| spawn(&mut entities));
+        let rel = HierarchyName::Faction;
+        entities.set_parent(rel, b, a).unwrap();
+        entities.set_parent(rel, c, b).unwrap();
+
+        assert_eq!(entities.set_parent(rel, a, c), Err(HierarchyError::Cycle));
+        assert_eq!(entities.set_parent(rel, a, a), Err(HierarchyError::Cycle));
+        assert!(entities[a].hierarchies.parent(rel).is_null());
+        assert_eq!(entities.root_of(rel, c), a);
+
+        // Other hierarchies are unaffected
+        entities.set_parent(HierarchyName::PlaceOf, a, c).unwrap();
+
+        assert_eq!(
+            entities.set_parent(rel, a, EntityId::null()),
+            Err(HierarchyError::MissingParent)
+        );
+        entities.despawn(c);
+        assert_eq!(
+            entities.set_parent(rel, c, a),
+            Err(HierarchyError::MissingChild)
+        );
+        assert!(
+            entities[a]
+                .hierarchies
+                .parent(HierarchyName::PlaceOf)
+                .is_null()
+        );
+        assert_eq!(entities[a].hierarchies.children(rel), &[b]);
+    }
+
+    #[test]
+    fn audit_passes_after_despawns() {
+        let arena = Arena::default();
+        let mut sim = crate::simulation::Simulation::new(7, &arena);
+        sim.entities.audit(&sim.sites).unwrap();
+
+        // A location bound to a site, a faction with members, and a person with links
+        let location = sim.entities.lookup("caer_ligualid");
+        let faction = sim.entities.lookup("rheged");
+        let person = sim.entities.with_flag(Flag::IsPerson)[0];
+        for id in [location, faction, person] {
+            crate::spawn::despawn(&mut sim, id);
+        }
+        crate::spawn::despawn_queued(&mut sim);
+
+        sim.entities.audit(&sim.sites).unwrap();
+        assert!(!sim.entities.contains(location));
+        assert_eq!(sim.entities.lookup("rheged"), EntityId::null());
+    }
+
+    #[test]
+    fn despawning_updates_the_indexes() {
+        let mut entities = Entities::default();
+        let [a, b, c] = [(); 3].map(|_| spawn(&mut entities));
+        for id in [a, b] {
+            entities.set_flags(id, &[Flag::IsPerson], true);
+            entities.set_kind(id, "Person");
+        }
+        entities.set_link(a, LinkName::Culture, c);
+        entities.set_link(b, LinkName::Culture, c);
+        entities.set_link(c, LinkName::Ruler, a);
+        assert_eq!(entities.with_flag(Flag::IsPerson), &[a, b]);
+        assert_eq!(entities.of_kind("Person"), &[a, b]);
+        assert_eq!(
+            entities.linked_from(c),
+            &[(a, LinkName::Culture), (b, LinkName::Culture)]
+        );
+
+        entities.despawn(a);
+        assert_eq!(entities.with_flag(Flag::IsPerson), &[b]);
+        assert_eq!(entities.of_kind("Person"), &[b]);
+        assert_eq!(entities.linked_from(c), &[(b, LinkName::Culture)]);
+        assert!(entities[c].links.get(LinkName::Ruler).is_null());
+        assert!(entities.linked_from(a).is_empty());
+
+        entities.despawn(c);
+        assert!(entities[b].links.get(LinkName::Culture).is_null());
+        assert!(entities.linked_from(c).is_empty());
+        assert_eq!(entities.of_kind("UNKNOWN_KIND"), &[]);
+    }
+
+    #[test]
+    fn links_can_point_at_several_entities() {
+        let mut entities = Entities::default();
+        let [a, b, c] = [(); 3].map(|_| spawn(&mut entities));
+        entities.add_link(a, LinkName::Ally, c);
+        entities.add_link(a, LinkName::Ally, b);
+        entities.add_link(a, LinkName::Ally, b);
+        entities.add_link(c, LinkName::Ally, b);
+        assert_eq!(
+            entities[a].links.all(LinkName::Ally).collect::<Vec<_>>(),
+            [b, c]
+        );
+        assert_eq!(entities[a].links.get(LinkName::Ally), b);
+        assert_eq!(
+            entities.sources(b, LinkName::Ally).collect::<Vec<_>>(),
+            [a, c]
+        );
+        assert_eq!(entities.sources(b, LinkName::Ruler).count(), 0);
+
+        entities.remove_link(a, LinkName::Ally, b);
+        assert_eq!(
+            entities[a].links.all(LinkName::Ally).collect::<Vec<_>>(),
+            [c]
+        );
+        assert_eq!(entities.sources(b, LinkName::Ally).collect::<Vec<_>>(), [c]);
 
-    pub fn pick_randomly(&self, list: NameList, rng: &mut SmallRng) -> &str {
-        let list = self.get(list);
-        let picked = rand::seq::SliceRandom::choose(list, rng);
-        picked.map(|x| x.as_str()).unwrap_or("NONAME")
+        // Setting a link leaves it pointing at the one target alone
+        entities.add_link(a, LinkName::Ally, b);
+        entities.set_link(a, LinkName::Ally, b);
+        assert_eq!(
+            entities[a].links.all(LinkName::Ally).collect::<Vec<_>>(),
+            [b]
+        );
+        assert!(entities.linked_from(c).is_empty());
+        entities.set_link(a, LinkName::Ally, EntityId::null());
+        assert_eq!(entities[a].links.all(LinkName::Ally).count(), 0);
+        assert_eq!(entities.sources(b, LinkName::Ally).collect::<Vec<_>>(), [c]);
     }
 }
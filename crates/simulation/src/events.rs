@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use strum::IntoStaticStr;
+
+use crate::entities::EntityId;
+
+/// How many events are kept; older ones are forgotten as new ones come in
+const HISTORY_LENGTH: usize = 500;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, IntoStaticStr)]
+pub(crate) enum EventKind {
+    /// A location took up a new culture
+    CultureChanged,
+    WarDeclared,
+    PeaceMade,
+    AllianceFormed,
+    TruceEnded,
+    /// A realm turned down what another one offered
+    OfferRefused,
+    /// A faction broke away from its liege
+    IndependenceDeclared,
+    FealtySworn,
+    /// A faction was absorbed by its liege
+    Annexed,
+    Died,
+    Married,
+    Born,
+    /// A faction got a new ruler, or was left without one
+    Succession,
+    /// An entity came into the world
+    Spawned,
+    /// An entity was removed from the world
+    Despawned,
+    /// Someone had a new entity brought into the world by one of their actions
+    Recruited,
+    /// An entity joined or left a parent in one of the hierarchies
+    ParentChanged,
+    /// The player had their agent take an action
+    ActionTaken,
+    TurnEnded,
+}
+
+impl EventKind {
+    /// Whether the event is part of the running of the world rather than of its history
+    pub fn is_routine(self) -> bool {
+        matches!(
+            self,
+            Self::Spawned | Self::Despawned | Self::ParentChanged | Self::TurnEnded
+        )
+    }
+}
+
+/// Something noteworthy that happened in the world
+pub(crate) struct Event {
+    pub turn: usize,
+    pub kind: EventKind,
+    /// Describes the event as it stood when it happened, as names may change later on
+    pub text: String,
+    /// The entities involved, the main one first
+    pub subjects: Vec<EntityId>,
+}
+
+/// The latest noteworthy things that happened, oldest first
+#[derive(Default)]
+pub(crate) struct Events {
+    entries: VecDeque<Event>,
+}
+
+impl Events {
+    pub fn record(&mut self, event: Event) {
+        if self.entries.len() == HISTORY_LENGTH {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(event);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Event> {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(turn: usize, kind: EventKind) -> Event {
+        Event {
+            turn,
+            kind,
+            text: String::new(),
+            subjects: vec![],
+        }
+    }
+
+    #[test]
+    fn only_the_latest_events_are_kept() {
+        let mut events = Events::default();
+        for turn in 0..HISTORY_LENGTH + 10 {
+            events.record(event(turn, EventKind::Died));
+        }
+        assert_eq!(events.iter().count(), HISTORY_LENGTH);
+        assert_eq!(events.iter().next().unwrap().turn, 10);
+        assert_eq!(events.iter().last().unwrap().turn, HISTORY_LENGTH + 9);
+    }
+
+    #[test]
+    fn routine_events_count_against_the_same_history() {
+        let mut events = Events::default();
+        events.record(event(0, EventKind::WarDeclared));
+        for turn in 1..HISTORY_LENGTH {
+            events.record(event(turn, EventKind::Spawned));
+        }
+        assert_eq!(events.iter().next().unwrap().kind, EventKind::WarDeclared);
+
+        // One more routine event pushes the war out of the history
+        events.record(event(HISTORY_LENGTH, EventKind::TurnEnded));
+        assert_eq!(events.iter().count(), HISTORY_LENGTH);
+        assert!(events.iter().all(|event| event.kind.is_routine()));
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+
+use rand::Rng;
+use rand::rngs::SmallRng;
+use slotmap::Key;
+use util::arena::Arena;
+
+use crate::diplomacy;
+use crate::entities::*;
+use crate::events::EventKind;
+use crate::life::Sex;
+use crate::movement::{self, PERSON_SPEED};
+use crate::names;
+use crate::simulation::Simulation;
+use crate::spawn::{self, SpawnEntity};
+
+/// Ages, in years, at which people look for someone to marry
+const MARRYING_AGES: std::ops::Range<f64> = 16.0..50.0;
+/// Chance of an unmarried man taking a wife from among the women of his realm in a turn
+const MARRIAGE_CHANCE: f64 = 0.2;
+/// Ages, in years, at which couples can have children
+const FERTILE_AGES: std::ops::Range<f64> = 16.0..45.0;
+/// Chance of a couple having a child in a turn
+const BIRTH_CHANCE: f64 = 0.06;
+
+/// Founds a dynasty named after the person, who becomes its first member
+pub(crate) fn found_dynasty(
+    sim: &mut Simulation,
+    founder: EntityId,
+    rng: &mut SmallRng,
+) -> EntityId {
+    let name = format!(
+        "House of {}",
+        names::given_name(&sim.entities[founder].name)
+    );
+    let info = SpawnEntity {
+        name: spawn::Name::Fixed(&name),
+        kind: "Dynasty",
+        flags: &[Flag::IsDynasty],
+        children: &[(HierarchyName::Dynasty, founder)],
+        ..Default::default()
+    };
+    info.spawn(sim, rng)
+}
+
+/// The parents of a person, the one they take their dynasty from first
+pub(crate) fn parents(entities: &Entities, person: EntityId) -> [EntityId; 2] {
+    let data = &entities[person];
+    [
+        data.hierarchies.parent(HierarchyName::Family),
+        data.links.get(LinkName::SecondParent),
+    ]
+}
+
+/// The children of a person, whichever parent they take their dynasty from
+pub(crate) fn children(entities: &Entities, person: EntityId) -> Vec<EntityId> {
+    let mut out = entities[person]
+        .hierarchies
+        .children(HierarchyName::Family)
+        .to_vec();
+    out.extend(entities.sources(person, LinkName::SecondParent));
+    out.sort();
+    out
+}
+
+/// Whether two people are too closely related to marry: parent and child, or siblings, even
+/// once the parents have died
+fn are_close_kin(entities: &Entities, a: EntityId, b: EntityId) -> bool {
+    let born_to = |id: EntityId| {
+        entities[id]
+            .life
+            .map_or([EntityId::null(); 2], |life| life.parents)
+    };
+    let parents_of_a = born_to(a);
+    let parents_of_b = born_to(b);
+    parents_of_a.contains(&b)
+        || parents_of_b.contains(&a)
+        || parents_of_a
+            .iter()
+            .any(|&parent| !parent.is_null() && parents_of_b.contains(&parent))
+}
+
+fn is_aged(entity: &EntityData, turn: usize, ages: &std::ops::Range<f64>) -> bool {
+    entity
+        .life
+        .is_some_and(|life| ages.contains(&life.age(turn)))
+}
+
+/// Marries off people of the same realm, has couples bring children into the world, and lets
+/// dynasties whose last member has died go
+pub(crate) fn update(sim: &mut Simulation, _: &Arena, rng: &mut SmallRng) {
+    let turn = sim.turn_number;
+
+    // Dead members were despawned after the systems that saw them die
+    let extinct: Vec<EntityId> = sim
+        .entities
+        .with_flag(Flag::IsDynasty)
+        .iter()
+        .copied()
+        .filter(|&id| {
+            sim.entities[id]
+                .hierarchies
+                .children(HierarchyName::Dynasty)
+                .is_empty()
+        })
+        .collect();
+    for dynasty in extinct {
+        spawn::despawn(sim, dynasty);
+    }
+
+    // People look for a spouse among those staying somewhere in the same realm, men and women
+    // apart
+    let mut unmarried: BTreeMap<EntityId, [Vec<EntityId>; 2]> = BTreeMap::new();
+    for &id in sim.entities.with_flag(Flag::IsPerson) {
+        let entity = &sim.entities[id];
+        let place = entity.hierarchies.parent(HierarchyName::PlaceOf);
+        let Some(life) = entity.life else {
+            continue;
+        };
+        if !place.is_null()
+            && entity.links.get(LinkName::Spouse).is_null()
+            && is_aged(entity, turn, &MARRYING_AGES)
+        {
+            let realm = diplomacy::realm_of(sim, id);
+            unmarried.entry(realm).or_default()[life.sex as usize].push(entity.id);
+        }
+    }
+
+    // Each man who takes a wife this turn weds the youngest woman left he is not close kin to
+    for [men, women] in unmarried.values_mut() {
+        women.sort_by_key(|&id| std::cmp::Reverse(sim.entities[id].life.map(|life| life.born)));
+        for &a in men.iter() {
+            if women.is_empty() {
+                break;
+            }
+            if !rng.gen_bool(MARRIAGE_CHANCE) {
+                continue;
+            }
+            let Some(idx) = women
+                .iter()
+                .position(|&b| !are_close_kin(&sim.entities, a, b))
+            else {
+                continue;
+            };
+            let b = women.remove(idx);
+            sim.entities.set_link(a, LinkName::Spouse, b);
+            sim.entities.set_link(b, LinkName::Spouse, a);
+
+            let text = format!("{} married {}", sim.entities[a].name, sim.entities[b].name);
+            sim.record_event(EventKind::Married, text, vec![a, b]);
+
+            // Brides from elsewhere set out for the place of their husband
+            let place = sim.entities[a].hierarchies.parent(HierarchyName::PlaceOf);
+            let site = sim.entities[place].bound_site;
+            if sim.entities[b].hierarchies.parent(HierarchyName::PlaceOf) != place
+                && !site.is_null()
+            {
+                movement::set_destination(sim, b, site);
+            }
+        }
+    }
+
+    // Couples are visited once, from the spouse with the lesser id
+    let couples: Vec<(EntityId, EntityId)> = sim
+        .entities
+        .iter()
+        .filter_map(|entity| {
+            let spouse = entity.links.get(LinkName::Spouse);
+            (!spouse.is_null() && entity.id < spouse).then_some((entity.id, spouse))
+        })
+        .filter(|&(a, b)| {
+            is_aged(&sim.entities[a], turn, &FERTILE_AGES)
+                && is_aged(&sim.entities[b], turn, &FERTILE_AGES)
+        })
+        .collect();
+
+    for (a, b) in couples {
+        if rng.gen_bool(BIRTH_CHANCE) {
+            give_birth(sim, a, b, rng);
+        }
+    }
+}
+
+/// Brings a child of the two into the world, wherever the parent whose dynasty it carries on
+/// happens to be. Children are not born on the road
+fn give_birth(sim: &mut Simulation, a: EntityId, b: EntityId, rng: &mut SmallRng) {
+    let dynasty_of = |id: EntityId| sim.entities[id].hierarchies.parent(HierarchyName::Dynasty);
+    let (parent, other) = if dynasty_of(a).is_null() && !dynasty_of(b).is_null() {
+        (b, a)
+    } else {
+        (a, b)
+    };
+    let father = if sim.entities[a]
+        .life
+        .is_some_and(|life| life.sex == Sex::Male)
+    {
+        a
+    } else {
+        b
+    };
+
+    let data = &sim.entities[parent];
+    let place = data.hierarchies.parent(HierarchyName::PlaceOf);
+    if place.is_null() {
+        return;
+    }
+    let culture = data.links.get(LinkName::Culture);
+    let religion = data.links.get(LinkName::Religion);
+    let faction = data.hierarchies.parent(HierarchyName::Faction);
+    let dynasty = data.hierarchies.parent(HierarchyName::Dynasty);
+    let aspects = sim.entities[culture].aspects.clone();
+
+    let info = SpawnEntity {
+        name: spawn::Name::Person { culture, father },
+        kind: "Person",
+        looks: spawn::Looks {
+            sprite: "person",
+            size: 1.,
+            color: spawn::Color::Dynamic,
+        },
+        flags: &[Flag::IsPerson],
+        links: &[
+            (LinkName::Culture, culture),
+            (LinkName::Religion, religion),
+            (LinkName::SecondParent, other),
+        ],
+        parents: &[
+            (HierarchyName::PlaceOf, place),
+            (HierarchyName::Faction, faction),
+            (HierarchyName::Family, parent),
+            (HierarchyName::Dynasty, dynasty),
+        ],
+        aspects: Some(&aspects),
+        speed: Some(PERSON_SPEED),
+        age: Some(0.),
+        sex: Sex::random(rng),
+        ..Default::default()
+    };
+    let child = info.spawn(sim, rng);
+    if let Some(life) = sim.entities[child].life.as_mut() {
+        life.parents = [parent, other];
+    }
+
+    let text = format!(
+        "{} was born to {} and {}",
+        sim.entities[child].name, sim.entities[parent].name, sim.entities[other].name
+    );
+    sim.record_event(EventKind::Born, text, vec![child, parent, other]);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::life;
+
+    #[test]
+    fn siblings_stay_kin_after_their_parents_die() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(2, &arena);
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        let place = sim.entities.lookup("caer_ligualid");
+        let people = sim.entities[place]
+            .hierarchies
+            .children(HierarchyName::PlaceOf)
+            .to_vec();
+        let (father, mother) = (people[0], people[1]);
+        give_birth(&mut sim, father, mother, &mut rng);
+        give_birth(&mut sim, father, mother, &mut rng);
+        let children = children(&sim.entities, father);
+        let &[a, b] = children.as_slice() else {
+            panic!("expected two children, found {}", children.len());
+        };
+
+        life::die(&mut sim, father, &mut rng);
+        life::die(&mut sim, mother, &mut rng);
+        spawn::despawn_queued(&mut sim);
+        assert_eq!(parents(&sim.entities, a), [EntityId::null(); 2]);
+
+        assert!(are_close_kin(&sim.entities, a, b));
+        assert!(!are_close_kin(&sim.entities, a, people[2]));
+
+        let mut bytes = vec![];
+        sim.save(&mut bytes).unwrap();
+        let loaded = Simulation::load(bytes.as_slice()).unwrap();
+        assert!(are_close_kin(&loaded.entities, a, b));
+    }
+}
@@ -1,260 +1,370 @@
-use macros::*;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use rand::Rng;
 use rand::rngs::SmallRng;
-use rand::{Rng, SeedableRng};
 use slotmap::Key;
 use util::arena::Arena;
+use util::intern::intern;
 use util::tagged::TaggedCollection;
-
+use util::tally::Tally;
+
+use crate::actions::{ActionDef, Effect, Requirement};
+use crate::aspects::AspectVector;
+use crate::economy::{Economy, Good};
+use crate::family;
+use crate::life::{self, Sex};
+use crate::movement::PERSON_SPEED;
+use crate::population::Population;
+use crate::rng::RngStream;
+use crate::scenario::*;
 use crate::simulation::*;
 use crate::spawn::{self, SpawnEntity};
+use crate::succession;
 use crate::{RGB, entities::*};
 
-pub(crate) fn init(sim: &mut Simulation, arena: &Arena, seed: u64) {
-    let rng = &mut SmallRng::seed_from_u64(seed);
+pub(crate) fn init(sim: &mut Simulation, arena: &Arena, scenario: &Scenario, report: &mut Report) {
+    let rng = &mut sim.rngs.stream(RngStream::Init);
     sim.turn_number = 1;
-    init_aspects(sim);
-    init_cultures(sim);
-    init_prototypes(sim);
-    init_sites(sim);
-    init_factions(sim, arena, rng);
-    let init_locations = init_locations(sim, arena, rng);
+    init_aspects(sim, &scenario.aspects, report);
+    init_religions(sim, &scenario.religions, report);
+    init_cultures(sim, &scenario.cultures, report);
+    init_prototypes(sim, &scenario.prototypes, report);
+    init_actions(sim, &scenario.actions, report);
+    init_sites(sim, &scenario.sites, report);
+    init_factions(sim, arena, &scenario.factions, report, rng);
+    let init_locations = init_locations(sim, arena, scenario, report, rng);
     init_people(sim, arena, &init_locations.create_people, rng);
-    init_cards(sim, arena, rng);
+    succession::crown_eldest(sim);
+    init_cards(sim, arena, &scenario.cards, report, rng);
 
+    // The history of the world starts with its first turn, not with it being put together
+    sim.events.clear();
     sim.tick(crate::TickRequest::default(), arena);
 }
 
+/// Looks up the entity of the tag, reporting it and moving on to the next entry if there is
+/// none or it is not of the kind asked for
 macro_rules! lookup_or_continue {
-    ($sim:expr, $tag:expr) => {{
-        let tag: &str = $tag;
-        let x = ($sim).entities.lookup(tag);
-        if x.is_null() {
+    ($sim:expr, $report:expr, $tag:expr, $kind:literal, $is_kind:expr) => {{
+        let tag: &Tag = $tag;
+        let is_kind: fn(&EntityData) -> bool = $is_kind;
+        let x = ($sim).entities.lookup(tag.as_ref());
+        if x.is_null() || !is_kind(&($sim).entities[x]) {
+            $report.error(tag, format!("Unknown {} '{}'", $kind, tag.as_ref()));
             continue;
         }
         x
     }};
-    ($sim:expr, $tag:expr, $kind:literal) => {{
-        let tag: &str = $tag;
-        let x = ($sim).entities.lookup(tag);
-        if x.is_null() {
-            println!("Unknown {} '{tag}'", $kind);
-            continue;
+}
+
+macro_rules! lookup_site_or_continue {
+    ($sim:expr, $report:expr, $tag:expr) => {{
+        let tag: &Tag = $tag;
+        match ($sim).sites.lookup(tag.as_ref()) {
+            Some(id) => id,
+            None => {
+                $report.error(tag, format!("Unknown site '{}'", tag.as_ref()));
+                continue;
+            }
         }
-        x
     }};
 }
 
-fn init_aspects(sim: &mut Simulation) {
-    struct Desc<'a> {
-        tag: &'a str,
-        name: &'a str,
-    }
+fn is_faction(data: &EntityData) -> bool {
+    data.flags.get(Flag::IsFaction)
+}
 
-    const DESC: &[Desc] = &[
-        Desc {
-            tag: "anglish",
-            name: "Anglish",
-        },
-        Desc {
-            tag: "brythonic",
-            name: "Brythonic",
-        },
-    ];
-
-    for desc in DESC {
-        sim.aspects.define(desc.tag, desc.name);
+/// Whether no entity goes by the tag yet, reporting it if one does
+fn tag_is_free(sim: &Simulation, tag: &Tag, report: &mut Report) -> bool {
+    if sim.entities.lookup(tag.as_ref()).is_null() {
+        return true;
     }
+    report.error(tag, format!("Duplicate tag '{}'", tag.as_ref()));
+    false
 }
 
-fn init_cultures(sim: &mut Simulation) {
-    struct Desc<'a> {
-        tag: &'a str,
-        name: &'a str,
-        names: &'a [&'a str],
+fn init_aspects(sim: &mut Simulation, descs: &[AspectDesc], report: &mut Report) {
+    for desc in descs {
+        if sim.aspects.lookup(desc.tag.as_ref()).tag == *desc.tag.as_ref() {
+            let message = format!("Duplicate aspect '{}'", desc.tag.as_ref());
+            report.error(&desc.tag, message);
+            continue;
+        }
+        sim.aspects.define(desc.tag.as_ref(), &desc.name);
     }
+}
 
-    const DESC: &[Desc] = &[
-        Desc {
-            tag: "anglish",
-            name: "Anglish",
-            names: crate::names::ANGLO_SAXON_MALE_NAMES,
-        },
-        Desc {
-            tag: "brythonic",
-            name: "Brythonic",
-            names: crate::names::BRYTHONIC_MALE_NAMES,
-        },
-    ];
-
-    for desc in DESC {
-        let entity = sim.entities.spawn_with_tag(desc.tag);
-        entity.name = desc.name.to_string();
-        entity.kind_name = "Culture";
-
-        entity.name_lists = {
-            let name_lists = NameLists::default().with(
-                NameList::PersonalNames,
-                desc.names.iter().map(|x| x.to_string()).collect(),
-            );
+fn init_religions(sim: &mut Simulation, descs: &[ReligionDesc], report: &mut Report) {
+    for desc in descs {
+        if !tag_is_free(sim, &desc.tag, report) {
+            continue;
+        }
+        let entity = sim.entities.spawn_with_tag(desc.tag.as_ref());
+        entity.name = desc.name.clone();
+        let entity = entity.id;
+        sim.entities.set_kind(entity, "Religion");
+    }
+}
 
-            Some(Box::new(name_lists))
-        };
+/// The religion of the given tag, reporting it if there is none
+fn lookup_religion(sim: &Simulation, tag: &Tag, report: &mut Report) -> EntityId {
+    let religion = sim.entities.lookup(tag.as_ref());
+    if religion.is_null() || sim.entities[religion].kind_name != "Religion" {
+        report.error(tag, format!("Unknown religion '{}'", tag.as_ref()));
+        return EntityId::null();
     }
+    religion
 }
 
-fn init_prototypes(sim: &mut Simulation) {
-    struct Desc<'a> {
-        tag: &'a str,
-        name: &'a str,
-        aspects: &'a [(&'a str, f64)],
+fn init_cultures(sim: &mut Simulation, descs: &[CultureDesc], report: &mut Report) {
+    for desc in descs {
+        if !tag_is_free(sim, &desc.tag, report) {
+            continue;
+        }
+        let builtin = |list: &Option<Tag>, report: &mut Report| -> Vec<String> {
+            let Some(list) = list else {
+                return vec![];
+            };
+            match crate::names::builtin(list.as_ref()) {
+                Some(list) => list.iter().map(|x| x.to_string()).collect(),
+                None => {
+                    report.error(list, format!("Unknown name list '{}'", list.as_ref()));
+                    vec![]
+                }
+            }
+        };
+        let mut names = builtin(&desc.name_list, report);
+        names.extend(desc.names.iter().cloned());
+        let female_names = builtin(&desc.female_name_list, report);
+
+        if names.is_empty() || female_names.is_empty() {
+            report.error(&desc.tag, "Culture has no names for men or for women");
+        }
+        for template in desc.son_of.iter().chain(&desc.daughter_of) {
+            if !template.contains("{}") {
+                let message = format!("Patronymic '{template}' has no '{{}}' for the father");
+                report.error(&desc.tag, message);
+            }
+        }
+
+        let mut name_lists = NameLists::default()
+            .with(NameList::MaleNames, names)
+            .with(NameList::FemaleNames, female_names)
+            .with(NameList::Epithets, builtin(&desc.epithet_list, report))
+            .with(NameList::PlaceNames, builtin(&desc.place_name_list, report));
+        name_lists.son_of = desc.son_of.clone();
+        name_lists.daughter_of = desc.daughter_of.clone();
+
+        let aspect = sim.aspects.lookup(desc.tag.as_ref());
+        if aspect.tag != *desc.tag.as_ref() {
+            let message = format!(
+                "Culture '{}' has no aspect of the same tag",
+                desc.tag.as_ref()
+            );
+            report.error(&desc.tag, message);
+            continue;
+        }
+        let aspect = aspect.id;
+        let mut aspects = AspectVector::new(&sim.aspects);
+        aspects.set(aspect, 1.);
+
+        let entity = sim.entities.spawn_with_tag(desc.tag.as_ref());
+        entity.name = desc.name.clone();
+        entity.aspects = aspects;
+
+        entity.name_lists = Some(Box::new(name_lists));
+        let entity = entity.id;
+        sim.entities.set_kind(entity, "Culture");
+
+        if let Some(religion) = &desc.religion {
+            let religion = lookup_religion(sim, religion, report);
+            sim.entities.set_link(entity, LinkName::Religion, religion);
+        }
     }
+}
 
-    const DESCS: &[Desc] = &[Desc {
-        tag: "bonheddwr",
-        name: "Bonheddwr",
-        aspects: &[],
-    }];
+fn init_prototypes(sim: &mut Simulation, descs: &[PrototypeDesc], report: &mut Report) {
+    for desc in descs {
+        if sim.prototypes.lookup(desc.tag.as_ref()).is_some() {
+            let message = format!("Duplicate prototype '{}'", desc.tag.as_ref());
+            report.error(&desc.tag, message);
+            continue;
+        }
+        let flags = parse_flags(&desc.flags, report);
+        if desc.speed.is_some() && !desc.has_location {
+            report.error(
+                &desc.tag,
+                "Prototypes that travel need a location to set out from",
+            );
+        }
 
-    for desc in DESCS {
-        let aspects = leak(sim.aspects.parse_vector(desc.aspects));
+        let mut aspects = Vec::with_capacity(desc.aspects.len());
+        for (tag, &amount) in &desc.aspects {
+            if sim.aspects.lookup(tag).tag != *tag {
+                report.error(&desc.tag, format!("Unknown aspect '{tag}'"));
+                continue;
+            }
+            aspects.push((tag.as_str(), amount));
+        }
+        let aspects = sim.aspects.parse_vector(&aspects);
 
         sim.prototypes.define(
-            desc.tag,
+            desc.tag.as_ref(),
             spawn::Prototype {
-                name: desc.name,
-                kind: "Card",
-                flags: &[Flag::IsCard],
+                name: intern(&desc.name),
+                kind: intern(&desc.kind),
+                flags,
                 aspects: Some(aspects),
-                has_location: true,
-                has_faction: false,
+                has_location: desc.has_location,
+                has_faction: desc.has_faction,
+                sprite: intern(&desc.sprite),
+                speed: desc.speed,
             },
         );
     }
 }
 
-fn leak<T: 'static>(x: T) -> &'static T {
-    Box::leak(Box::new(x))
+fn parse_flags(tags: &[Tag], report: &mut Report) -> Vec<Flag> {
+    let mut flags = Vec::with_capacity(tags.len());
+    for flag in tags {
+        match Flag::from_str(flag.as_ref()) {
+            Ok(flag) => flags.push(flag),
+            Err(_) => report.error(flag, format!("Unknown flag '{}'", flag.as_ref())),
+        }
+    }
+    flags
 }
 
-fn init_sites(sim: &mut Simulation) {
-    struct Desc {
-        tag: &'static str,
-        pos: (f32, f32),
-    }
+fn init_actions(sim: &mut Simulation, descs: &[ActionDesc], report: &mut Report) {
+    for desc in descs {
+        if sim
+            .actions
+            .iter()
+            .any(|action| action.tag == *desc.tag.as_ref())
+        {
+            let message = format!("Duplicate action '{}'", desc.tag.as_ref());
+            report.error(&desc.tag, message);
+            continue;
+        }
+        let requirement = |req: &RequirementDesc, report: &mut Report| {
+            let mut aspects = Vec::with_capacity(req.aspects.len());
+            for (tag, &at_least) in &req.aspects {
+                let aspect = sim.aspects.lookup(tag);
+                if aspect.tag != *tag {
+                    report.error(&desc.tag, format!("Unknown aspect '{tag}'"));
+                    continue;
+                }
+                aspects.push((aspect.id, at_least));
+            }
+            Requirement {
+                flags: parse_flags(&req.flags, report),
+                kind: req.kind.clone(),
+                travels: req.travels,
+                aspects,
+            }
+        };
+        let subject = requirement(&desc.subject, report);
+        let target = requirement(&desc.target, report);
+
+        let mut effects = Vec::with_capacity(desc.effects.len());
+        for effect in &desc.effects {
+            let effect = match effect {
+                EffectDesc::Spawn { prototype, at } => {
+                    if sim.prototypes.lookup(prototype).is_none() {
+                        report.error(&desc.tag, format!("Unknown prototype '{prototype}'"));
+                        continue;
+                    }
+                    Effect::Spawn {
+                        prototype: prototype.clone(),
+                        at: *at,
+                    }
+                }
+                &EffectDesc::SetParent {
+                    hierarchy,
+                    child,
+                    parent,
+                } => Effect::SetParent {
+                    hierarchy,
+                    child,
+                    parent,
+                },
+                EffectDesc::Travel => Effect::Travel,
+                EffectDesc::Stockpile { of, good, amount } => {
+                    let Ok(good) = Good::from_str(good) else {
+                        report.error(&desc.tag, format!("Unknown good '{good}'"));
+                        continue;
+                    };
+                    Effect::Stockpile {
+                        of: *of,
+                        good,
+                        amount: *amount,
+                    }
+                }
+                &EffectDesc::Relation { amount } => Effect::Relation { amount },
+            };
+            effects.push(effect);
+        }
 
-    const DESCS: &[Desc] = &[
-        Desc {
-            tag: "caer_ligualid",
-            pos: (0., 0.),
-        },
-        Desc {
-            tag: "din_drust",
-            pos: (-7., -9.),
-        },
-        Desc {
-            tag: "anava",
-            pos: (7., -5.),
-        },
-        Desc {
-            tag: "llan_heledd",
-            pos: (3., 12.),
-        },
-        Desc {
-            tag: "caer_ligualid-din_drust",
-            pos: (-4., -4.),
-        },
-        Desc {
-            tag: "caer_ligualid_south",
-            pos: (0., 8.),
-        },
-        Desc {
-            tag: "isura",
-            pos: (-13., -8.),
-        },
-        Desc {
-            tag: "isura_west",
-            pos: (-19.5, -10.),
-        },
-        Desc {
-            tag: "din_rheged",
-            pos: (-25., -8.4),
-        },
-        Desc {
-            tag: "ad_candidam_casam",
-            pos: (-19., -6.2),
-        },
-    ];
-
-    for desc in DESCS {
-        sim.sites.define(desc.tag, desc.pos.into());
+        sim.actions.push(ActionDef {
+            tag: desc.tag.as_ref().clone(),
+            name: desc.name.clone(),
+            subject,
+            target,
+            relations: desc
+                .relations
+                .iter()
+                .map(|relation| (relation.hierarchy, relation.kinship))
+                .collect(),
+            max_distance: desc.max_distance,
+            cost: parse_goods(&desc.tag, &desc.cost, report).iter().collect(),
+            effects,
+        });
     }
+}
 
-    const CONNECTIONS: &[(&str, &str)] = &[
-        ("caer_ligualid", "anava"),
-        ("din_drust", "anava"),
-        ("caer_ligualid", "caer_ligualid_south"),
-        ("caer_ligualid_south", "llan_heledd"),
-        ("caer_ligualid", "caer_ligualid-din_drust"),
-        ("din_drust", "caer_ligualid-din_drust"),
-        ("din_drust", "isura"),
-        ("isura", "isura_west"),
-        ("isura_west", "din_rheged"),
-        ("isura_west", "ad_candidam_casam"),
-    ];
-
-    for (tag1, tag2) in CONNECTIONS {
-        let id1 = get_or_continue!(sim.sites.lookup(&tag1), "Unknown site '{tag1}'");
-        let id2 = get_or_continue!(sim.sites.lookup(&tag2), "Unknown site '{tag2}'");
-        sim.sites.graph.connect(id1, id2);
+fn init_sites(sim: &mut Simulation, descs: &[SiteDesc], report: &mut Report) {
+    for desc in descs {
+        if sim.sites.lookup(desc.tag.as_ref()).is_some() {
+            report.error(&desc.tag, format!("Duplicate site '{}'", desc.tag.as_ref()));
+            continue;
+        }
+        sim.sites.define(desc.tag.as_ref(), desc.pos.into());
     }
-}
 
-fn init_factions(sim: &mut Simulation, arena: &Arena, rng: &mut SmallRng) {
-    struct Desc {
-        tag: &'static str,
-        name: &'static str,
-        parent: &'static str,
-        color: (u8, u8, u8),
+    for desc in descs {
+        for tag in &desc.connections {
+            let id1 = lookup_site_or_continue!(sim, report, &desc.tag);
+            let id2 = lookup_site_or_continue!(sim, report, tag);
+            sim.sites.graph.connect(id1, id2);
+        }
     }
+}
 
-    const DESCS: &[Desc] = &[
-        Desc {
-            tag: "rheged",
-            name: "Rheged",
-            parent: "",
-            color: (200, 40, 30),
-        },
-        Desc {
-            tag: "clan_drust",
-            name: "Clan Drust",
-            parent: "rheged",
-            color: (0, 0, 0),
-        },
-        Desc {
-            tag: "clan_heledd",
-            name: "Clan Heledd",
-            parent: "rheged",
-            color: (0, 0, 0),
-        },
-    ];
-
-    for desc in DESCS {
-        let color = if desc.color == (0, 0, 0) {
-            random_color(rng)
-        } else {
-            let (r, g, b) = desc.color;
-            RGB { r, g, b }
+fn init_factions(
+    sim: &mut Simulation,
+    arena: &Arena,
+    descs: &[FactionDesc],
+    report: &mut Report,
+    rng: &mut SmallRng,
+) {
+    for desc in descs {
+        if !tag_is_free(sim, &desc.tag, report) {
+            continue;
+        }
+        let color = match desc.color {
+            Some((r, g, b)) => RGB { r, g, b },
+            None => random_color(rng),
         };
 
-        let parent = if desc.parent.is_empty() {
-            EntityId::null()
-        } else {
-            lookup_or_continue!(sim, desc.parent)
+        let parent = match &desc.parent {
+            Some(parent) => lookup_or_continue!(sim, report, parent, "faction", is_faction),
+            None => EntityId::null(),
         };
 
         let info = spawn::SpawnEntity {
-            tag: desc.tag,
-            name: spawn::Name::Fixed(desc.name),
+            tag: desc.tag.as_ref(),
+            name: spawn::Name::Fixed(&desc.name),
             kind: "Faction",
             looks: spawn::Looks {
                 color: spawn::Color::Fixed(color),
@@ -264,7 +374,8 @@ fn init_factions(sim: &mut Simulation, arena: &Arena, rng: &mut SmallRng) {
             parents: arena.alloc_slice([(HierarchyName::Faction, parent)]),
             ..Default::default()
         };
-        info.spawn(sim, rng);
+        let faction = info.spawn(sim, rng);
+        sim.entities[faction].succession = Some(desc.succession);
     }
 }
 
@@ -273,114 +384,128 @@ struct InitLocations {
     create_people: Vec<CreatePeople>,
 }
 
-fn init_locations(sim: &mut Simulation, arena: &Arena, rng: &mut SmallRng) -> InitLocations {
+fn init_locations(
+    sim: &mut Simulation,
+    arena: &Arena,
+    scenario: &Scenario,
+    report: &mut Report,
+    rng: &mut SmallRng,
+) -> InitLocations {
     let mut out = InitLocations::default();
 
-    struct Desc {
-        name: &'static str,
-        site: &'static str,
-        culture: &'static str,
-        kind: Kind,
-        faction: &'static str,
+    let mut kinds: BTreeMap<&str, (&LocationKindDesc, Economy)> = BTreeMap::new();
+    for kind in &scenario.location_kinds {
+        let tag = kind.tag.as_ref().as_str();
+        if kinds.contains_key(tag) {
+            report.error(&kind.tag, format!("Duplicate location kind '{tag}'"));
+            continue;
+        }
+        let economy = Economy {
+            stockpile: parse_goods(&kind.tag, &kind.stockpile, report),
+            production: parse_goods(&kind.tag, &kind.production, report),
+            consumption: parse_goods(&kind.tag, &kind.consumption, report),
+            ..Default::default()
+        };
+        kinds.insert(tag, (kind, economy));
     }
 
-    enum Kind {
-        Town,
-        Village,
-        Hillfort,
-    }
+    out.create_people.reserve(scenario.locations.len());
 
-    const DESCS: &[Desc] = &[
-        Desc {
-            name: "Caer Ligualid",
-            site: "caer_ligualid",
-            culture: "brythonic",
-            kind: Kind::Town,
-            faction: "rheged",
-        },
-        Desc {
-            name: "Anava",
-            site: "anava",
-            culture: "brythonic",
-            kind: Kind::Village,
-            faction: "rheged",
-        },
-        Desc {
-            name: "Din Drust",
-            site: "din_drust",
-            culture: "brythonic",
-            kind: Kind::Hillfort,
-            faction: "clan_drust",
-        },
-        Desc {
-            name: "Llan Heledd",
-            site: "llan_heledd",
-            culture: "brythonic",
-            kind: Kind::Village,
-            faction: "clan_heledd",
-        },
-    ];
-
-    out.create_people.reserve(DESCS.len());
-
-    for desc in DESCS {
-        let faction = lookup_or_continue!(sim, desc.faction, "faction");
-        let culture = lookup_or_continue!(sim, desc.culture, "culture");
-        let site = get_or_continue!(sim.sites.lookup_data_mut(desc.site), "Unknown site").id;
-
-        struct KindData {
-            name: &'static str,
-            image: &'static str,
-            size: f32,
-            create_n_people: usize,
+    for desc in &scenario.locations {
+        let faction = lookup_or_continue!(sim, report, &desc.faction, "faction", is_faction);
+        let culture = lookup_or_continue!(sim, report, &desc.culture, "culture", |x| {
+            x.kind_name == "Culture"
+        });
+        let religion = match &desc.religion {
+            Some(religion) => lookup_religion(sim, religion, report),
+            None => sim.entities[culture].links.get(LinkName::Religion),
+        };
+        let site = lookup_site_or_continue!(sim, report, &desc.site);
+
+        let (kind, economy) = match kinds.get(desc.kind.as_ref().as_str()) {
+            Some(kind) => kind,
+            None => {
+                let message = format!("Unknown location kind '{}'", desc.kind.as_ref());
+                report.error(&desc.kind, message);
+                continue;
+            }
+        };
+
+        if !sim.sites.data[site].bound_entity.is_null() {
+            let message = format!("Site '{}' already has a location", desc.site.as_ref());
+            report.error(&desc.site, message);
+            continue;
+        }
+        if !tag_is_free(sim, &desc.site, report) {
+            continue;
         }
 
-        let kind = match desc.kind {
-            Kind::Town => KindData {
-                name: "Town",
-                image: "town",
-                size: 2.,
-                create_n_people: 5,
-            },
-            Kind::Village => KindData {
-                name: "Village",
-                image: "village",
-                size: 1.4,
-                create_n_people: 3,
-            },
-            Kind::Hillfort => KindData {
-                name: "Hillfort",
-                image: "hillfort",
-                size: 1.75,
-                create_n_people: 3,
-            },
+        let name = match &desc.name {
+            Some(name) => name.clone(),
+            None => crate::names::place_name(sim, culture, rng),
         };
-
         let info = SpawnEntity {
-            tag: desc.site,
-            name: spawn::Name::Fixed(desc.name),
-            kind: kind.name,
+            tag: desc.site.as_ref(),
+            name: spawn::Name::Fixed(&name),
+            kind: intern(&kind.name),
             looks: spawn::Looks {
-                sprite: kind.image,
+                sprite: intern(&kind.sprite),
                 size: kind.size,
                 color: spawn::Color::Dynamic,
             },
             site,
             flags: &[Flag::IsLocation, Flag::IsPlace],
-            links: arena.alloc_slice([(LinkName::Culture, culture)]),
+            links: arena
+                .alloc_slice([(LinkName::Culture, culture), (LinkName::Religion, religion)]),
             parents: &[(HierarchyName::Faction, faction)],
             children: &[(HierarchyName::Capital, faction)],
             ..Default::default()
         };
         let location = info.spawn(sim, rng);
+        sim.entities[location].economy = Some(Box::new(economy.clone()));
+        sim.entities[location].population = {
+            let mut population = Population {
+                capacity: kind.capacity,
+                ..Default::default()
+            };
+            match &desc.population {
+                Some(by_culture) => {
+                    for (culture, &heads) in by_culture {
+                        let aspect = sim.aspects.lookup(culture.as_ref());
+                        if aspect.tag != *culture.as_ref() {
+                            let message = format!("Unknown culture '{}'", culture.as_ref());
+                            report.error(culture, message);
+                            continue;
+                        }
+                        population.by_culture.set(aspect.id, heads);
+                    }
+                }
+                None => {
+                    let aspect = sim.aspects.lookup(desc.culture.as_ref()).id;
+                    population.by_culture.set(aspect, kind.population);
+                }
+            }
+            Some(Box::new(population))
+        };
         out.create_people.push(CreatePeople {
             location,
-            num_people: kind.create_n_people,
+            num_people: kind.people,
         });
     }
     out
 }
 
+fn parse_goods(tag: &Tag, amounts: &BTreeMap<String, f64>, report: &mut Report) -> Tally<Good> {
+    let mut out = Tally::new();
+    for (good, &amount) in amounts {
+        match Good::from_str(good) {
+            Ok(good) => out.set(good, amount),
+            Err(_) => report.error(tag, format!("Unknown good '{good}'")),
+        }
+    }
+    out
+}
+
 struct CreatePeople {
     location: EntityId,
     num_people: usize,
@@ -390,52 +515,86 @@ fn init_people(sim: &mut Simulation, arena: &Arena, sources: &[CreatePeople], rn
     let mut spawns = vec![];
 
     for desc in sources {
-        for _ in 0..desc.num_people {
+        // Every place starts out with as many men as women, give or take one
+        let first = Sex::random(rng);
+        for idx in 0..desc.num_people {
             let location = &sim.entities[desc.location];
             let culture = location.links.get(LinkName::Culture);
+            let religion = location.links.get(LinkName::Religion);
             let faction = location.hierarchies.parent(HierarchyName::Faction);
 
             let location = location.id;
-            let name = spawn::Name::FromList(culture, NameList::PersonalNames);
+            let name = spawn::Name::Person {
+                culture,
+                father: EntityId::null(),
+            };
 
             spawns.push(SpawnEntity {
                 name,
                 kind: "Person",
-                looks: spawn::Looks::default(),
+                looks: spawn::Looks {
+                    sprite: "person",
+                    size: 1.,
+                    color: spawn::Color::Dynamic,
+                },
                 site: Default::default(),
                 flags: &[Flag::IsPerson],
-                links: arena.alloc_slice([(LinkName::Culture, culture)]),
+                links: arena
+                    .alloc_slice([(LinkName::Culture, culture), (LinkName::Religion, religion)]),
                 parents: arena.alloc_slice([
                     (HierarchyName::PlaceOf, location),
                     (HierarchyName::Faction, faction),
                 ]),
                 children: &[],
+                speed: Some(PERSON_SPEED),
+                age: Some(life::random_starting_age(rng)),
+                sex: if idx % 2 == 0 { first } else { first.other() },
                 ..Default::default()
             });
         }
     }
 
+    // People start out with the aspects of their culture, each the founder of a dynasty
     for spawn in spawns {
-        spawn.spawn(sim, rng);
+        let culture = spawn.links[0].1;
+        let person = spawn.spawn(sim, rng);
+        sim.entities[person].aspects = sim.entities[culture].aspects.clone();
+        family::found_dynasty(sim, person, rng);
     }
 }
 
-fn init_cards(sim: &mut Simulation, arena: &Arena, rng: &mut SmallRng) {
-    struct Desc {
-        prototype: &'static str,
-        location: &'static str,
-    }
-
-    const DESCS: &[Desc] = &[Desc {
-        prototype: "bonheddwr",
-        location: "caer_ligualid",
-    }];
+fn init_cards(
+    sim: &mut Simulation,
+    arena: &Arena,
+    descs: &[CardDesc],
+    report: &mut Report,
+    rng: &mut SmallRng,
+) {
+    for desc in descs {
+        let location = lookup_or_continue!(sim, report, &desc.location, "location", |x| {
+            x.flags.get(Flag::IsLocation)
+        });
 
-    for desc in DESCS {
-        let location = lookup_or_continue!(sim, desc.location, "location");
+        let prototype = match sim.prototypes.lookup(desc.prototype.as_ref()) {
+            Some(prototype) => prototype.clone(),
+            None => {
+                let message = format!("Unknown prototype '{}'", desc.prototype.as_ref());
+                report.error(&desc.prototype, message);
+                continue;
+            }
+        };
 
-        let prototype =
-            get_or_continue!(sim.prototypes.lookup(desc.prototype), "Undefined prototype");
+        let faction = sim.entities[location]
+            .hierarchies
+            .parent(HierarchyName::Faction);
+        if prototype.has_faction && faction.is_null() {
+            let message = format!(
+                "Location '{}' belongs to no faction",
+                desc.location.as_ref()
+            );
+            report.error(&desc.location, message);
+            continue;
+        }
 
         prototype.spawn(
             sim,
@@ -443,16 +602,139 @@ fn init_cards(sim: &mut Simulation, arena: &Arena, rng: &mut SmallRng) {
             rng,
             &spawn::PrototypeArgs {
                 location,
+                faction,
                 ..Default::default()
             },
         );
     }
 }
 
-fn random_color(rng: &mut SmallRng) -> RGB {
+pub(crate) fn random_color(rng: &mut SmallRng) -> RGB {
     RGB {
         r: rng.gen_range(u8::MIN..=u8::MAX),
         g: rng.gen_range(u8::MIN..=u8::MAX),
         b: rng.gen_range(u8::MIN..=u8::MAX),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::rng::Rngs;
+    use crate::scenario::{self, DEFAULT};
+
+    use super::*;
+
+    /// Line and message of every error reported for the scenario, in order
+    fn errors(source: &str) -> Vec<(usize, String)> {
+        let errors = match Simulation::from_scenario(source, 1, &Arena::default()) {
+            Ok(_) => panic!("the scenario is accepted"),
+            Err(errors) => errors,
+        };
+        errors
+            .0
+            .into_iter()
+            .map(|err| (err.line, err.message))
+            .collect()
+    }
+
+    /// 1-based line of the first occurrence of the text in the source
+    fn line_of(source: &str, text: &str) -> usize {
+        let offset = source.find(text).expect("the text is in the source");
+        source[..offset].lines().count() + 1
+    }
+
+    #[test]
+    fn malformed_toml_is_reported_at_its_line() {
+        let source = DEFAULT.replacen("name = \"Brythonic\"", "name = Brythonic", 1);
+        let errors = errors(&source);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, line_of(&source, "name = Brythonic"));
+
+        let source = DEFAULT.replacen("name = \"Anglish\"", "colour = \"white\"", 1);
+        let errors = self::errors(&source);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, line_of(&source, "colour"));
+        assert!(
+            errors[0].1.contains("unknown field `colour`"),
+            "{}",
+            errors[0].1
+        );
+    }
+
+    #[test]
+    fn unknown_tags_are_reported_where_they_are_used() {
+        let source = DEFAULT.replacen("faction = \"clan_heledd\"", "faction = \"deira\"", 1);
+        assert_eq!(
+            errors(&source),
+            vec![(
+                line_of(&source, "faction = \"deira\""),
+                "Unknown faction 'deira'".to_string()
+            )]
+        );
+
+        // A tag naming something of the wrong kind is as good as unknown
+        let source = DEFAULT.replacen("faction = \"clan_heledd\"", "faction = \"old_gods\"", 1);
+        assert_eq!(
+            errors(&source),
+            vec![(
+                line_of(&source, "faction = \"old_gods\""),
+                "Unknown faction 'old_gods'".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn duplicate_tags_are_reported_against_the_later_entry() {
+        let source =
+            format!("{DEFAULT}\n[[religions]]\ntag = \"christianity\"\nname = \"Christianity\"\n");
+        let line = source.lines().count() - 1;
+        assert_eq!(
+            errors(&source),
+            vec![(line, "Duplicate tag 'christianity'".to_string())]
+        );
+    }
+
+    #[test]
+    fn errors_are_listed_in_the_order_of_their_lines() {
+        let source = DEFAULT
+            .replacen("faction = \"clan_heledd\"", "faction = \"deira\"", 1)
+            .replacen("religion = \"old_gods\"", "religion = \"woden\"", 1);
+        assert_eq!(
+            errors(&source),
+            vec![
+                (
+                    line_of(&source, "religion = \"woden\""),
+                    "Unknown religion 'woden'".to_string()
+                ),
+                (
+                    line_of(&source, "faction = \"deira\""),
+                    "Unknown faction 'deira'".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn cultures_without_an_aspect_are_left_out() {
+        let source = format!(
+            "{DEFAULT}\n[[cultures]]\ntag = \"pictish\"\nname = \"Pictish\"\n\
+             name_list = \"brythonic_male\"\nfemale_name_list = \"brythonic_female\"\n"
+        );
+        let scenario = scenario::parse(&source).unwrap();
+        let mut report = Report::new(&source);
+        let mut sim = Simulation {
+            rngs: Rngs::new(1),
+            ..Default::default()
+        };
+        init(&mut sim, &Arena::default(), &scenario, &mut report);
+
+        assert!(sim.entities.lookup("pictish").is_null());
+        let errors = report.finish().unwrap_err().0;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, line_of(&source, "tag = \"pictish\""));
+        assert_eq!(
+            errors[0].message,
+            "Culture 'pictish' has no aspect of the same tag"
+        );
+    }
+}
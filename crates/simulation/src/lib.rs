@@ -10,11 +10,38 @@ pub use tick::*;
 mod view;
 pub use view::*;
 
+mod replay;
+pub use replay::{CommandLog, Divergence, ReplayError};
+
+mod save;
+pub use save::SaveError;
+
+mod scenario;
+pub use scenario::{ScenarioError, ScenarioErrors};
+
+mod summary;
+pub use summary::{FactionSummary, Summary};
+
 pub use spatial::geom::{Extents, V2};
 
+mod actions;
+mod ai;
 mod aspects;
+mod culture;
+mod diplomacy;
+mod economy;
 mod entities;
+mod events;
+mod family;
 mod init;
+mod life;
+mod movement;
 mod names;
+mod population;
+mod query;
+mod rng;
 mod sites;
 mod spawn;
+mod succession;
+mod systems;
+mod vassals;
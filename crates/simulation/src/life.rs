@@ -0,0 +1,238 @@
+use rand::Rng;
+use rand::rngs::SmallRng;
+use serde::{Deserialize, Serialize};
+use slotmap::Key;
+use util::arena::Arena;
+
+use crate::entities::EntityId;
+use crate::events::EventKind;
+use crate::simulation::Simulation;
+use crate::spawn;
+use crate::succession;
+
+/// A turn is a season
+pub(crate) const TURNS_PER_YEAR: usize = 4;
+/// Ages people are given at the start of the world
+const STARTING_AGES: std::ops::Range<f64> = 16.0..60.0;
+/// Chance of a newborn dying within a year; mortality grows exponentially with age from there
+const BASE_MORTALITY: f64 = 0.0005;
+/// How quickly mortality grows with every year of age
+const MORTALITY_GROWTH: f64 = 0.085;
+/// How much more likely to die someone in the worst health is, against someone in full health
+const FRAILTY: f64 = 4.;
+/// Age up to which people can keep in full health
+const PRIME_AGE: f64 = 30.;
+/// Health that can no longer be kept up with every year past the prime age
+const HEALTH_DECLINE: f64 = 0.012;
+/// Share of the way health recovers towards what age allows each turn
+const RECOVERY_RATE: f64 = 0.1;
+/// Chance of falling ill in a turn, and how much health an illness can cost at most
+const ILLNESS_CHANCE: f64 = 0.03;
+const ILLNESS_SEVERITY: f64 = 0.5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub(crate) enum Sex {
+    #[default]
+    Male,
+    Female,
+}
+
+impl Sex {
+    /// Either, as likely as the other
+    pub fn random(rng: &mut SmallRng) -> Self {
+        if rng.gen_bool(0.5) {
+            Self::Male
+        } else {
+            Self::Female
+        }
+    }
+
+    pub fn other(self) -> Self {
+        match self {
+            Self::Male => Self::Female,
+            Self::Female => Self::Male,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Male => "Male",
+            Self::Female => "Female",
+        }
+    }
+}
+
+/// The lifetime of a mortal entity
+#[derive(Clone, Copy)]
+pub(crate) struct Life {
+    /// Turn of birth, which comes before the first turn for those who were born before the
+    /// world started
+    pub born: i64,
+    /// From 0 (dying) to 1 (full health)
+    pub health: f64,
+    pub sex: Sex,
+    /// Those the person was born to, which are kept after they die so that kinship is not
+    /// forgotten along with them. Null for those born before the world started
+    pub parents: [EntityId; 2],
+}
+
+impl Life {
+    /// Starts the life of someone who is already the given number of years old
+    pub fn aged(turn: usize, years: f64, sex: Sex) -> Self {
+        Self {
+            born: turn as i64 - (years * TURNS_PER_YEAR as f64).round() as i64,
+            health: 1.,
+            sex,
+            parents: [EntityId::null(); 2],
+        }
+    }
+
+    /// Age in years
+    pub fn age(&self, turn: usize) -> f64 {
+        (turn as i64 - self.born) as f64 / TURNS_PER_YEAR as f64
+    }
+
+    /// The best health one can keep up at the given age
+    fn vigour(age: f64) -> f64 {
+        (1. - (age - PRIME_AGE).max(0.) * HEALTH_DECLINE).max(0.)
+    }
+
+    /// Chance of dying within the turn
+    fn mortality(&self, age: f64) -> f64 {
+        let yearly = BASE_MORTALITY * (MORTALITY_GROWTH * age).exp();
+        let frailty = 1. + (1. - self.health) * (FRAILTY - 1.);
+        (yearly * frailty / TURNS_PER_YEAR as f64).min(1.)
+    }
+}
+
+pub(crate) fn random_starting_age(rng: &mut SmallRng) -> f64 {
+    rng.gen_range(STARTING_AGES)
+}
+
+/// Ages everyone mortal by a turn: health recovers towards what their age allows, illnesses
+/// strike at random, and some die, more so the older and sicker they are
+pub(crate) fn age_all(sim: &mut Simulation, _: &Arena, rng: &mut SmallRng) {
+    let turn = sim.turn_number;
+    let mut deaths = vec![];
+
+    for entity in sim.entities.iter_mut() {
+        let Some(life) = entity.life.as_mut() else {
+            continue;
+        };
+        let age = life.age(turn);
+
+        life.health += (Life::vigour(age) - life.health) * RECOVERY_RATE;
+        if rng.gen_bool(ILLNESS_CHANCE) {
+            life.health -= rng.gen_range(0.0..ILLNESS_SEVERITY);
+        }
+        life.health = life.health.clamp(0., 1.);
+
+        if life.health == 0. || rng.gen_bool(life.mortality(age)) {
+            deaths.push(entity.id);
+        }
+    }
+
+    for id in deaths {
+        die(sim, id, rng);
+    }
+}
+
+/// Has the person die: their death is recorded, whatever they ruled passes on, and they leave
+/// the world at the next safe point
+pub(crate) fn die(sim: &mut Simulation, id: EntityId, rng: &mut SmallRng) {
+    let age = sim.entities[id]
+        .life
+        .map_or(0., |life| life.age(sim.turn_number));
+    let text = format!(
+        "{} died at the age of {:.0}",
+        sim.entities[id].name,
+        age.floor()
+    );
+    sim.record_event(EventKind::Died, text, vec![id]);
+    succession::succeed(sim, id, rng);
+    spawn::despawn(sim, id);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::entities::{Flag, HierarchyName, LinkName};
+    use crate::events::EventKind;
+
+    #[test]
+    fn several_can_die_in_the_same_turn() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        // Two members of an elective faction past any hope of recovery, the first of them its
+        // ruler, and one in the prime of life
+        let faction = sim.entities.lookup("bernicia");
+        let members: Vec<_> = sim.entities[faction]
+            .hierarchies
+            .children(HierarchyName::Faction)
+            .iter()
+            .copied()
+            .filter(|&id| sim.entities[id].flags.get(Flag::IsPerson))
+            .collect();
+        let &[ruler, heir, survivor] = members.as_slice() else {
+            panic!("expected three members, found {}", members.len());
+        };
+        let turn = sim.turn_number;
+        for id in [ruler, heir] {
+            sim.entities[id].life = Some(Life {
+                health: 0.,
+                ..Life::aged(turn, 120., Sex::Male)
+            });
+        }
+        sim.entities[survivor].life = Some(Life::aged(turn, 20., Sex::Male));
+        sim.entities.set_link(faction, LinkName::Ruler, ruler);
+
+        age_all(&mut sim, &arena, &mut rng);
+
+        let died: Vec<_> = sim
+            .events
+            .iter()
+            .filter(|event| event.kind == EventKind::Died)
+            .map(|event| event.subjects[0])
+            .collect();
+        assert!(died.contains(&ruler) && died.contains(&heir));
+        assert!(!died.contains(&survivor));
+        assert!(spawn::is_despawning(&sim, ruler) && spawn::is_despawning(&sim, heir));
+        assert_eq!(sim.entities[faction].links.get(LinkName::Ruler), survivor);
+
+        spawn::despawn_queued(&mut sim);
+        assert!(!sim.entities.contains(ruler) && !sim.entities.contains(heir));
+        assert_eq!(sim.entities[faction].links.get(LinkName::Ruler), survivor);
+    }
+
+    #[test]
+    fn health_stays_within_bounds_and_follows_age() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        let people = sim.entities.with_flag(Flag::IsPerson).to_vec();
+        for &id in &people {
+            sim.entities[id].life = Some(Life {
+                health: 0.5,
+                ..Life::aged(sim.turn_number, 20., Sex::Male)
+            });
+        }
+        for _ in 0..TURNS_PER_YEAR * 5 {
+            age_all(&mut sim, &arena, &mut rng);
+            sim.turn_number += 1;
+        }
+
+        let mut total = 0.;
+        for &id in &people {
+            let health = sim.entities[id].life.unwrap().health;
+            assert!((0. ..=1.).contains(&health));
+            total += health;
+        }
+        // Illnesses come and go, but the young mostly recover towards full health
+        assert!(total / people.len() as f64 > 0.8);
+    }
+}
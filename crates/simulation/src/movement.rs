@@ -0,0 +1,214 @@
+use rand::rngs::SmallRng;
+use slotmap::Key;
+use spatial::geom::V2;
+use util::arena::Arena;
+
+use crate::entities::*;
+use crate::events::EventKind;
+use crate::simulation::Simulation;
+use crate::sites::{SiteId, Sites};
+
+/// Distance a person on foot covers in a turn
+pub(crate) const PERSON_SPEED: f32 = 6.;
+
+/// Where an entity that can travel along the site graph is, and where it is going
+pub(crate) struct Movement {
+    /// The site the entity is at, or the last one it went through when on the road
+    pub site: SiteId,
+    /// Sites still to go through, the next one last
+    pub route: Vec<SiteId>,
+    /// Distance covered from `site` towards the next site on the route
+    pub progress: f32,
+    /// Distance covered in a turn
+    pub speed: f32,
+}
+
+impl Movement {
+    pub fn new(site: SiteId, speed: f32) -> Self {
+        Self {
+            site,
+            route: vec![],
+            progress: 0.,
+            speed,
+        }
+    }
+
+    pub fn is_travelling(&self) -> bool {
+        !self.route.is_empty()
+    }
+
+    pub fn next_site(&self) -> SiteId {
+        self.route.last().copied().unwrap_or_default()
+    }
+
+    /// The site the entity will stop at, which is the one it is at when not travelling
+    pub fn destination(&self) -> SiteId {
+        self.route.first().copied().unwrap_or(self.site)
+    }
+
+    pub fn position(&self, sites: &Sites) -> V2 {
+        let from = sites.pos_of(self.site);
+        if !self.is_travelling() {
+            return from;
+        }
+        let to = sites.pos_of(self.next_site());
+        let length = sites.graph.distance(self.site, self.next_site());
+        let t = if length > 0. {
+            (self.progress / length).clamp(0., 1.)
+        } else {
+            1.
+        };
+        V2::new(from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t)
+    }
+}
+
+/// Sends the entity towards the destination along the shortest route. Entities already on the
+/// road first finish the stretch they are on. Returns false if the entity cannot travel, or
+/// there is no route to the destination
+pub(crate) fn set_destination(sim: &mut Simulation, entity: EntityId, destination: SiteId) -> bool {
+    let Some(movement) = sim
+        .entities
+        .get_mut(entity)
+        .and_then(|e| e.movement.as_mut())
+    else {
+        return false;
+    };
+
+    // Routes start from the next site when midway through a stretch of road
+    let start = if movement.is_travelling() {
+        movement.next_site()
+    } else {
+        movement.site
+    };
+
+    let Some((steps, _)) = sim.sites.graph.astar(start, destination) else {
+        return false;
+    };
+
+    let mut route: Vec<SiteId> = steps.into_iter().skip(1).collect();
+    if movement.is_travelling() {
+        route.insert(0, start);
+    }
+    route.reverse();
+
+    let departing = !movement.is_travelling() && !route.is_empty();
+    movement.route = route;
+
+    let place = sim.entities[entity]
+        .hierarchies
+        .parent(HierarchyName::PlaceOf);
+    if departing && !place.is_null() {
+        sim.entities.unparent(HierarchyName::PlaceOf, entity);
+        let text = format!(
+            "{} left {}",
+            sim.entities[entity].name, sim.entities[place].name
+        );
+        sim.record_event(EventKind::ParentChanged, text, vec![entity, place]);
+    }
+    true
+}
+
+/// Moves every travelling entity forward by a turn's worth of road
+pub(crate) fn advance_all(sim: &mut Simulation, arena: &Arena, _: &mut SmallRng) {
+    let travelling = arena.alloc_iter(
+        sim.entities
+            .iter()
+            .filter(|entity| entity.movement.as_ref().is_some_and(|m| m.is_travelling()))
+            .map(|entity| entity.id),
+    );
+
+    for &mut id in travelling {
+        let movement = sim.entities[id].movement.as_mut().unwrap();
+        advance(&sim.sites, movement);
+
+        if movement.is_travelling() {
+            continue;
+        }
+
+        // Arrived: join the place at the destination, if there is one
+        let place = sim.sites.data[movement.site].bound_entity;
+        if !place.is_null() && sim.entities[place].flags.get(Flag::IsPlace) {
+            // A place cannot take in the entity it is itself inside of, which then stays on
+            // the site
+            if sim
+                .entities
+                .set_parent(HierarchyName::PlaceOf, id, place)
+                .is_ok()
+            {
+                let text = format!(
+                    "{} arrived at {}",
+                    sim.entities[id].name, sim.entities[place].name
+                );
+                sim.record_event(EventKind::ParentChanged, text, vec![id, place]);
+            }
+        }
+    }
+}
+
+fn advance(sites: &Sites, movement: &mut Movement) {
+    let mut budget = movement.speed;
+    while budget > 0. && movement.is_travelling() {
+        let next = movement.next_site();
+        let left = sites.graph.distance(movement.site, next) - movement.progress;
+        if budget < left {
+            movement.progress += budget;
+            break;
+        }
+        budget -= left;
+        movement.site = next;
+        movement.progress = 0.;
+        movement.route.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use util::tagged::TaggedCollection;
+
+    use super::*;
+
+    fn movement(sim: &Simulation, id: EntityId) -> &Movement {
+        sim.entities[id].movement.as_ref().unwrap()
+    }
+
+    #[test]
+    fn travellers_reach_their_destination_and_stay_there() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(3, &arena);
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        let home = sim.entities.lookup("caer_ligualid");
+        let traveller = sim.entities[home]
+            .hierarchies
+            .children(HierarchyName::PlaceOf)
+            .iter()
+            .copied()
+            .find(|&id| sim.entities[id].movement.is_some())
+            .unwrap();
+        let destination = sim.sites.lookup("gefrin").unwrap();
+
+        assert!(set_destination(&mut sim, traveller, destination));
+        let place_of = |sim: &Simulation| {
+            sim.entities[traveller]
+                .hierarchies
+                .parent(HierarchyName::PlaceOf)
+        };
+        assert!(place_of(&sim).is_null());
+
+        let mut turns = 0;
+        while movement(&sim, traveller).is_travelling() {
+            advance_all(&mut sim, &arena, &mut rng);
+            turns += 1;
+            assert!(turns < 1000, "the traveller never arrived");
+        }
+        assert!(turns > 1);
+        assert_eq!(movement(&sim, traveller).site, destination);
+        assert!(!place_of(&sim).is_null());
+        assert_eq!(place_of(&sim), sim.sites.data[destination].bound_entity);
+
+        // Nothing moves those who are not travelling
+        advance_all(&mut sim, &arena, &mut rng);
+        assert_eq!(movement(&sim, traveller).site, destination);
+    }
+}
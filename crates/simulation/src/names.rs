@@ -1,4 +1,147 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use rand::Rng;
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use slotmap::Key;
+
+use crate::entities::*;
+use crate::life::Sex;
+use crate::simulation::Simulation;
+
+/// Share of names made up after those a culture knows, rather than taken from among them
+const INVENTED_SHARE: f64 = 0.4;
+/// Shortest name worth making up
+const MIN_INVENTED_LENGTH: usize = 3;
+/// Tries at making up a name that is not already known before settling for a known one
+const INVENTION_ATTEMPTS: usize = 10;
+/// Tries at coming up with a name nobody else goes by. Giving up takes far more people than
+/// the names of a culture can tell apart
+const NAMING_ATTEMPTS: usize = 20;
+
+/// A full name for a person of the culture: a given name, followed by that of the father where
+/// the culture names people after him, and by an epithet where it takes one to tell them apart
+/// from someone living. Those whose father is unknown are given one
+pub(crate) fn person_name(
+    sim: &Simulation,
+    culture: EntityId,
+    sex: Sex,
+    father: EntityId,
+    rng: &mut SmallRng,
+) -> String {
+    let Some(lists) = sim.entities[culture].name_lists.as_deref() else {
+        return "NONAME".to_string();
+    };
+    let taken: BTreeSet<&str> = sim
+        .entities
+        .with_flag(Flag::IsPerson)
+        .iter()
+        .map(|&id| sim.entities[id].name.as_str())
+        .collect();
+    let (list, template) = match sex {
+        Sex::Male => (NameList::MaleNames, &lists.son_of),
+        Sex::Female => (NameList::FemaleNames, &lists.daughter_of),
+    };
+    let father = match template {
+        Some(_) if father.is_null() => pick_or_invent(lists, NameList::MaleNames, rng),
+        Some(_) => given_name(&sim.entities[father].name).to_string(),
+        None => String::new(),
+    };
+
+    let mut name = String::new();
+    for _ in 0..NAMING_ATTEMPTS {
+        name = pick_or_invent(lists, list, rng);
+        if let Some(template) = template {
+            name = format!("{name} {}", patronymic(template, &father));
+        }
+        if !taken.contains(name.as_str()) {
+            return name;
+        }
+        if let Some(epithet) = lists.get(NameList::Epithets).choose(rng) {
+            let named = format!("{name} {epithet}");
+            if !taken.contains(named.as_str()) {
+                return named;
+            }
+        }
+    }
+    name
+}
+
+/// A name for a place of the culture, unlike that of any other place
+pub(crate) fn place_name(sim: &Simulation, culture: EntityId, rng: &mut SmallRng) -> String {
+    let Some(lists) = sim.entities[culture].name_lists.as_deref() else {
+        return "NONAME".to_string();
+    };
+    let taken: BTreeSet<&str> = sim
+        .entities
+        .with_flag(Flag::IsPlace)
+        .iter()
+        .map(|&id| sim.entities[id].name.as_str())
+        .collect();
+
+    let mut name = String::new();
+    for _ in 0..NAMING_ATTEMPTS {
+        name = pick_or_invent(lists, NameList::PlaceNames, rng);
+        if !taken.contains(name.as_str()) {
+            break;
+        }
+    }
+    name
+}
+
+/// The name a person was given, without whatever follows it
+pub(crate) fn given_name(name: &str) -> &str {
+    name.split(' ').next().unwrap_or(name)
+}
+
+/// One of the names in the list, or now and then a new one made up after them
+fn pick_or_invent(lists: &NameLists, list: NameList, rng: &mut SmallRng) -> String {
+    let known = lists.get(list);
+    if rng.gen_bool(INVENTED_SHARE) {
+        for _ in 0..INVENTION_ATTEMPTS {
+            if let Some(name) = lists.chain(list).generate(rng)
+                && name.len() >= MIN_INVENTED_LENGTH
+                && !known.contains(&name)
+            {
+                return name;
+            }
+        }
+    }
+    known
+        .choose(rng)
+        .cloned()
+        .unwrap_or_else(|| "NONAME".to_string())
+}
+
+/// Fills in the father's name, dropping the vowel it ends on where the template carries on
+/// with one, so that the son of Ida is an Iding
+fn patronymic(template: &str, father: &str) -> String {
+    let is_vowel = |c: char| "aeiouy".contains(c.to_ascii_lowercase());
+    let (before, after) = template.split_once("{}").unwrap_or((template, ""));
+    let father = match father.strip_suffix(is_vowel) {
+        Some(stem) if after.starts_with(is_vowel) && !stem.is_empty() => stem,
+        _ => father,
+    };
+    format!("{before}{father}{after}")
+}
+
 type List = &'static [&'static str];
+
+/// Looks up one of the name lists built into the game by its scenario tag
+pub(crate) fn builtin(tag: &str) -> Option<List> {
+    match tag {
+        "anglo_saxon_male" => Some(ANGLO_SAXON_MALE_NAMES),
+        "anglo_saxon_female" => Some(ANGLO_SAXON_FEMALE_NAMES),
+        "anglo_saxon_epithets" => Some(ANGLO_SAXON_EPITHETS),
+        "anglo_saxon_places" => Some(ANGLO_SAXON_PLACE_NAMES),
+        "brythonic_male" => Some(BRYTHONIC_MALE_NAMES),
+        "brythonic_female" => Some(BRYTHONIC_FEMALE_NAMES),
+        "brythonic_epithets" => Some(BRYTHONIC_EPITHETS),
+        "brythonic_places" => Some(BRYTHONIC_PLACE_NAMES),
+        _ => None,
+    }
+}
+
 pub(crate) const ANGLO_SAXON_MALE_NAMES: List = &[
     "Acca",
     "Aelfgar",
@@ -219,3 +362,280 @@ pub(crate) const BRYTHONIC_MALE_NAMES: List = &[
     "Urien",
     "Ynyr",
 ];
+
+pub(crate) const ANGLO_SAXON_FEMALE_NAMES: List = &[
+    "Aebbe",
+    "Aelfflaed",
+    "Aelfgifu",
+    "Aelfthryth",
+    "Aethelburh",
+    "Aethelflaed",
+    "Aethelthryth",
+    "Bebbe",
+    "Beorhtgifu",
+    "Cwenburh",
+    "Cyneburh",
+    "Cynethryth",
+    "Cynewise",
+    "Eadburh",
+    "Eadgifu",
+    "Eadgyth",
+    "Eafa",
+    "Ealdgyth",
+    "Eanflaed",
+    "Eanswith",
+    "Eormenburh",
+    "Eormengyth",
+    "Hereswith",
+    "Hild",
+    "Leofgifu",
+    "Leofrun",
+    "Mildburh",
+    "Mildthryth",
+    "Osthryth",
+    "Seaxburh",
+    "Wihtburh",
+    "Wulfrun",
+    "Wynflaed",
+];
+
+pub(crate) const BRYTHONIC_FEMALE_NAMES: List = &[
+    "Angharad",
+    "Arianrhod",
+    "Branwen",
+    "Ceinwen",
+    "Creirwy",
+    "Dwynwen",
+    "Efa",
+    "Eigr",
+    "Elen",
+    "Eleri",
+    "Ffreuer",
+    "Gwawr",
+    "Gwenddydd",
+    "Gwenllian",
+    "Gwenhwyfar",
+    "Gwladus",
+    "Heledd",
+    "Indeg",
+    "Lleucu",
+    "Madrun",
+    "Nest",
+    "Nefyn",
+    "Penarwen",
+    "Rhiannon",
+    "Tangwystl",
+    "Tegau",
+    "Tudful",
+];
+
+pub(crate) const ANGLO_SAXON_EPITHETS: List = &[
+    "the Bold",
+    "the Elder",
+    "the Fair",
+    "the Grim",
+    "the Old",
+    "the Red",
+    "the Strong",
+    "the Tall",
+    "the Wise",
+    "the Young",
+    "Flamebearer",
+    "Ironhand",
+    "Wolfheart",
+];
+
+pub(crate) const BRYTHONIC_EPITHETS: List = &[
+    "Ddu", "Foel", "Frych", "Fychan", "Goch", "Gul", "Hael", "Hen", "Hir", "Llwyd", "Mawr",
+    "Mwynfawr",
+];
+
+pub(crate) const ANGLO_SAXON_PLACE_NAMES: List = &[
+    "Aescesdun",
+    "Bebbanburh",
+    "Beoranwudu",
+    "Brunanburh",
+    "Ceasterford",
+    "Cnearesburh",
+    "Denisesburn",
+    "Eoforwic",
+    "Gefrin",
+    "Hagustaldesham",
+    "Heathfeld",
+    "Hreopandun",
+    "Lindisfarena",
+    "Maelmin",
+    "Streoneshalh",
+    "Tinamuthe",
+    "Twifyrde",
+    "Wealtun",
+    "Wincanheal",
+    "Wintanceaster",
+];
+
+pub(crate) const BRYTHONIC_PLACE_NAMES: List = &[
+    "Aberffraw",
+    "Alclud",
+    "Caer Faddon",
+    "Caer Greu",
+    "Caer Luel",
+    "Catraeth",
+    "Din Eidyn",
+    "Din Guayrdi",
+    "Dunragit",
+    "Llanfair",
+    "Lloegyr",
+    "Medcaut",
+    "Penrhyn",
+    "Penrhyd",
+    "Porth Wygyr",
+    "Rheon",
+    "Trefriw",
+];
+
+/// Makes up names that look like those it was trained on, by stringing together letters in the
+/// way they follow each other there
+#[derive(Default)]
+pub(crate) struct Markov {
+    /// Letters seen after each run of `ORDER` letters, as often as they were seen there. Runs
+    /// at the start of a name are padded with `START`
+    next: BTreeMap<[char; ORDER], Vec<char>>,
+}
+
+/// Letters the chain looks back at to pick the next one
+const ORDER: usize = 3;
+const START: char = '^';
+const END: char = '$';
+/// Longest name the chain may come up with
+const MAX_LENGTH: usize = 14;
+
+impl Markov {
+    pub fn train(names: &[String]) -> Self {
+        let mut next: BTreeMap<[char; ORDER], Vec<char>> = BTreeMap::new();
+        for name in names {
+            let mut run = [START; ORDER];
+            for c in name.chars().chain([END]) {
+                next.entry(run).or_default().push(c);
+                run.rotate_left(1);
+                run[ORDER - 1] = c;
+            }
+        }
+        Self { next }
+    }
+
+    /// A new name, or nothing if the chain was trained on nothing or rambled on too long
+    pub fn generate(&self, rng: &mut SmallRng) -> Option<String> {
+        let mut out = String::new();
+        let mut run = [START; ORDER];
+        loop {
+            let c = *self.next.get(&run)?.choose(rng)?;
+            if c == END {
+                return Some(out);
+            }
+            if out.len() >= MAX_LENGTH {
+                return None;
+            }
+            out.push(c);
+            run.rotate_left(1);
+            run[ORDER - 1] = c;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use util::arena::Arena;
+
+    use super::*;
+
+    fn names(seed: u64) -> Vec<String> {
+        let sim = Simulation::new(seed, &Arena::default());
+        sim.entities
+            .iter()
+            .map(|entity| entity.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn worlds_are_named_after_their_seed() {
+        assert_eq!(names(4), names(4));
+        assert_ne!(names(4), names(5));
+    }
+
+    #[test]
+    fn names_follow_the_rng() {
+        let sim = Simulation::new(4, &Arena::default());
+        let culture = sim.entities.lookup("brythonic");
+        let draw = |seed: u64| {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let mut out = vec![place_name(&sim, culture, &mut rng)];
+            for sex in [Sex::Male, Sex::Female].repeat(10) {
+                out.push(person_name(&sim, culture, sex, EntityId::null(), &mut rng));
+            }
+            out
+        };
+        assert_eq!(draw(1), draw(1));
+        assert_ne!(draw(1), draw(2));
+    }
+
+    #[test]
+    fn patronymics_drop_a_vowel_before_another() {
+        assert_eq!(patronymic("{}ing", "Ida"), "Iding");
+        assert_eq!(patronymic("ap {}", "Cadfan"), "ap Cadfan");
+        assert_eq!(patronymic("{}ing", "A"), "Aing");
+    }
+
+    #[test]
+    fn invented_names_stay_within_the_letters_and_lengths_of_the_culture() {
+        let sim = Simulation::new(4, &Arena::default());
+        let mut rng = SmallRng::seed_from_u64(0);
+        for tag in ["anglish", "brythonic"] {
+            let lists = sim.entities[sim.entities.lookup(tag)]
+                .name_lists
+                .as_deref()
+                .unwrap();
+            for list in [
+                NameList::MaleNames,
+                NameList::FemaleNames,
+                NameList::PlaceNames,
+            ] {
+                let known = lists.get(list);
+                let letters: BTreeSet<char> = known.iter().flat_map(|name| name.chars()).collect();
+                for _ in 0..200 {
+                    let name = pick_or_invent(lists, list, &mut rng);
+                    assert!(name.chars().all(|c| letters.contains(&c)), "{name}");
+                    if !known.contains(&name) {
+                        assert!(name.len() >= MIN_INVENTED_LENGTH, "{name}");
+                        assert!(name.chars().count() <= MAX_LENGTH, "{name}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn people_are_named_after_their_father() {
+        let sim = Simulation::new(4, &Arena::default());
+        let mut rng = SmallRng::seed_from_u64(0);
+        let anglish = sim.entities.lookup("anglish");
+        let brythonic = sim.entities.lookup("brythonic");
+        let father = sim.entities.with_flag(Flag::IsPerson)[0];
+        let given = given_name(&sim.entities[father].name);
+
+        for _ in 0..20 {
+            let son = person_name(&sim, brythonic, Sex::Male, father, &mut rng);
+            assert!(son.contains(&format!(" ap {given}")), "{son}");
+            let daughter = person_name(&sim, brythonic, Sex::Female, father, &mut rng);
+            assert!(daughter.contains(&format!(" ferch {given}")), "{daughter}");
+            let son = person_name(&sim, anglish, Sex::Male, father, &mut rng);
+            assert!(son.contains(&patronymic("{}ing", given)), "{son}");
+            // The Anglish do not name their daughters after their father
+            let daughter = person_name(&sim, anglish, Sex::Female, father, &mut rng);
+            assert!(
+                !daughter.split(' ').skip(1).any(|part| part.contains(given)),
+                "{daughter}"
+            );
+        }
+    }
+}
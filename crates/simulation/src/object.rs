@@ -5,7 +5,7 @@ use slotmap::Key;
 use crate::entities::EntityId;
 use crate::sites::SiteId;
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Debug)]
 pub struct ObjectId(pub(crate) ObjectHandle);
 
 impl ObjectId {
@@ -33,7 +33,7 @@ impl ObjectId {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub(crate) enum ObjectHandle {
     Null,
     Global,
@@ -48,9 +48,10 @@ impl Default for ObjectHandle {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, PartialEq, Debug)]
 pub struct Object(BTreeMap<String, Value>);
 
+#[derive(PartialEq, Debug)]
 pub(crate) enum Value {
     Id(ObjectId),
     Flag(bool),
@@ -0,0 +1,223 @@
+use rand::rngs::SmallRng;
+use slotmap::Key;
+use util::arena::Arena;
+use util::tally::Tally;
+
+use crate::aspects::AspectId;
+use crate::economy::Good;
+use crate::entities::*;
+use crate::simulation::Simulation;
+
+/// Share of a well-fed population added each turn, while far below capacity
+const GROWTH_RATE: f64 = 0.02;
+/// Share of the people left without food that die each turn
+const STARVATION_RATE: f64 = 0.25;
+/// Share of the people left without food that leave for another location each turn
+const MIGRATION_RATE: f64 = 0.4;
+/// Cultures with fewer people than this at a location die out there
+const MIN_HEADS: f64 = 1.;
+
+/// The commoners living at a location
+#[derive(Default, Clone)]
+pub(crate) struct Population {
+    /// Headcount by culture, keyed by the aspect of the culture
+    pub by_culture: Tally<AspectId>,
+    /// Population at which growth stops
+    pub capacity: f64,
+    /// Births over the last turn
+    pub born: f64,
+    /// Deaths from hunger over the last turn
+    pub starved: f64,
+    /// Arrivals minus departures over the last turn
+    pub migrated: f64,
+}
+
+impl Population {
+    pub fn total(&self) -> f64 {
+        self.by_culture
+            .iter()
+            .fold(0., |acc, (_, heads)| acc + heads)
+    }
+
+    /// Removes the given number of people, spread across cultures by their share of the
+    /// total. Returns who was removed, by culture
+    fn take(&mut self, heads: f64) -> Tally<AspectId> {
+        let mut taken = Tally::new();
+        let total = self.total();
+        if total <= 0. {
+            return taken;
+        }
+        let share = (heads / total).min(1.);
+        for (culture, count) in self.by_culture.clone().iter() {
+            taken.set(culture, count * share);
+            self.by_culture.set(culture, count * (1. - share));
+        }
+        taken
+    }
+
+    fn add(&mut self, people: &Tally<AspectId>) {
+        for (culture, heads) in people.iter() {
+            self.by_culture.add_one(culture, heads);
+        }
+    }
+}
+
+/// Has every location grow, starve, and send off the people it cannot feed
+pub(crate) fn update(sim: &mut Simulation, _: &Arena, _: &mut SmallRng) {
+    let locations: Vec<EntityId> = sim
+        .entities
+        .iter()
+        .filter(|entity| entity.population.is_some())
+        .map(|entity| entity.id)
+        .collect();
+
+    // The locations that people can move to are the ones that had enough food this turn
+    let fed: Vec<EntityId> = locations
+        .iter()
+        .copied()
+        .filter(|&id| unfed(&sim.entities[id]) == 0.)
+        .collect();
+
+    for &id in &locations {
+        let population = sim.entities[id].population.as_mut().unwrap();
+        population.born = 0.;
+        population.starved = 0.;
+        population.migrated = 0.;
+    }
+
+    let mut migrations = vec![];
+
+    for &id in &locations {
+        let hungry = unfed(&sim.entities[id]);
+        let population = sim.entities[id].population.as_mut().unwrap();
+
+        if hungry == 0. {
+            // Logistic growth, slowing down as the location fills up
+            let total = population.total();
+            let rate = GROWTH_RATE * (1. - total / population.capacity.max(1.)).max(0.);
+            population.born = total * rate;
+            for (culture, heads) in population.by_culture.clone().iter() {
+                population.by_culture.set(culture, heads * (1. + rate));
+            }
+            continue;
+        }
+
+        population.starved = population
+            .take(hungry * STARVATION_RATE)
+            .iter()
+            .map(|x| x.1)
+            .sum();
+
+        let destination = nearest_of(sim, id, &fed);
+        if !destination.is_null() {
+            let population = sim.entities[id].population.as_mut().unwrap();
+            let migrants = population.take(hungry * MIGRATION_RATE);
+            migrations.push((id, destination, migrants));
+        }
+    }
+
+    for (source, destination, migrants) in migrations {
+        let heads: f64 = migrants.iter().map(|(_, heads)| heads).sum();
+        sim.entities[source].population.as_mut().unwrap().migrated -= heads;
+
+        let population = sim.entities[destination].population.as_mut().unwrap();
+        population.add(&migrants);
+        population.migrated += heads;
+    }
+
+    for &id in &locations {
+        let population = sim.entities[id].population.as_mut().unwrap();
+        for (culture, heads) in population.by_culture.clone().iter() {
+            if heads < MIN_HEADS {
+                population.by_culture.set(culture, 0.);
+            }
+        }
+    }
+}
+
+/// Number of people that the location could not feed over the last turn
+fn unfed(location: &EntityData) -> f64 {
+    let Some(economy) = &location.economy else {
+        return 0.;
+    };
+    let shortfall = economy.shortfall.get(Good::Grain);
+    let per_head = economy.consumption.get(Good::Grain);
+    if shortfall <= 0. || per_head <= 0. {
+        return 0.;
+    }
+    shortfall / per_head
+}
+
+/// The location among the candidates that is closest to the given one along the site graph
+fn nearest_of(sim: &Simulation, from: EntityId, candidates: &[EntityId]) -> EntityId {
+    let start = sim.entities[from].bound_site;
+    candidates
+        .iter()
+        .filter_map(|&candidate| {
+            let end = sim.entities[candidate].bound_site;
+            let (_, cost) = sim.sites.graph.astar(start, end)?;
+            Some((candidate, cost))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(candidate, _)| candidate)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_hungry_starve_or_leave_for_the_nearest_fed_location() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let mut rng = sim.rngs.stream(crate::rng::RngStream::Interaction);
+        let locations = sim.entities.with_flag(Flag::IsLocation).to_vec();
+        for &id in &locations {
+            sim.entities[id].economy.as_mut().unwrap().shortfall = Tally::new();
+        }
+
+        // A hundred people at the town go without grain this turn
+        let town = sim.entities.lookup("caer_ligualid");
+        let economy = sim.entities[town].economy.as_mut().unwrap();
+        economy.consumption.set(Good::Grain, 0.5);
+        economy.shortfall.set(Good::Grain, 50.);
+        let before = sim.entities[town].population.clone().unwrap();
+        let everyone = |sim: &Simulation| -> f64 {
+            let populations = locations.iter().map(|&id| &sim.entities[id].population);
+            populations.map(|p| p.as_ref().unwrap().total()).sum()
+        };
+        let total = everyone(&sim);
+
+        update(&mut sim, &arena, &mut rng);
+
+        let after = sim.entities[town].population.as_ref().unwrap();
+        assert_eq!(after.starved, 100. * STARVATION_RATE);
+        assert_eq!(after.migrated, -100. * MIGRATION_RATE);
+        assert_eq!(after.born, 0.);
+        let left = before.total() - 100. * (STARVATION_RATE + MIGRATION_RATE);
+        assert!((after.total() - left).abs() < 1e-6);
+        // Every culture bears its share of the losses
+        for (culture, heads) in before.by_culture.iter() {
+            let expected = heads * left / before.total();
+            assert!((after.by_culture.get(culture) - expected).abs() < 1e-6);
+        }
+
+        // The migrants all went to one place, and nobody else was lost
+        let arrivals: Vec<_> = locations
+            .iter()
+            .copied()
+            .filter(|&id| sim.entities[id].population.as_ref().unwrap().migrated > 0.)
+            .collect();
+        let others: Vec<_> = locations.iter().copied().filter(|&id| id != town).collect();
+        assert_eq!(arrivals, [nearest_of(&sim, town, &others)]);
+        let arrived = sim.entities[arrivals[0]].population.as_ref().unwrap();
+        assert!((arrived.migrated - 100. * MIGRATION_RATE).abs() < 1e-6);
+        let born: f64 = locations
+            .iter()
+            .map(|&id| sim.entities[id].population.as_ref().unwrap().born)
+            .sum();
+        let expected = total - 100. * STARVATION_RATE + born;
+        assert!((everyone(&sim) - expected).abs() < 1e-6);
+    }
+}
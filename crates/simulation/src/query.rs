@@ -0,0 +1,325 @@
+use std::collections::VecDeque;
+
+use slotmap::{Key, SecondaryMap};
+use util::arena::Arena;
+
+use crate::aspects::AspectId;
+use crate::entities::*;
+use crate::simulation::Simulation;
+use crate::sites::SiteId;
+
+/// A search for entities, built up out of filters that must all hold
+pub(crate) struct Query<'a> {
+    sim: &'a Simulation,
+    /// Only look among the children of this entity, rather than every entity
+    children_of: Option<(HierarchyName, EntityId)>,
+    flags: &'a [Flag],
+    any_flags: &'a [Flag],
+    kind: Option<&'a str>,
+    mobile: bool,
+    links: Vec<(LinkName, EntityId)>,
+    below: Vec<(HierarchyName, EntityId)>,
+    /// A site and the most steps along the site graph an entity can be from it
+    near: Option<(SiteId, usize)>,
+    aspects: Vec<(AspectId, f64)>,
+}
+
+impl<'a> Query<'a> {
+    pub fn new(sim: &'a Simulation) -> Self {
+        Self {
+            sim,
+            children_of: None,
+            flags: &[],
+            any_flags: &[],
+            kind: None,
+            mobile: false,
+            links: vec![],
+            below: vec![],
+            near: None,
+            aspects: vec![],
+        }
+    }
+
+    /// Direct children of the parent in the hierarchy
+    pub fn children_of(mut self, rel: HierarchyName, parent: EntityId) -> Self {
+        self.children_of = Some((rel, parent));
+        self
+    }
+
+    /// Has every one of the flags
+    pub fn with_flags(mut self, flags: &'a [Flag]) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Has at least one of the flags
+    pub fn with_any_flag(mut self, flags: &'a [Flag]) -> Self {
+        self.any_flags = flags;
+        self
+    }
+
+    pub fn of_kind(mut self, kind: &'a str) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Can travel along the site graph
+    pub fn mobile(mut self) -> Self {
+        self.mobile = true;
+        self
+    }
+
+    pub fn linked_to(mut self, link: LinkName, target: EntityId) -> Self {
+        self.links.push((link, target));
+        self
+    }
+
+    /// Sits anywhere below the ancestor in the hierarchy
+    pub fn below(mut self, rel: HierarchyName, ancestor: EntityId) -> Self {
+        self.below.push((rel, ancestor));
+        self
+    }
+
+    /// Is no more than the given number of steps along the site graph away from the site. Nothing
+    /// is near a null site
+    pub fn within(mut self, site: SiteId, steps: usize) -> Self {
+        self.near = Some((site, steps));
+        self
+    }
+
+    /// Has at least the given amount of the aspect
+    pub fn with_aspect(mut self, aspect: AspectId, at_least: f64) -> Self {
+        self.aspects.push((aspect, at_least));
+        self
+    }
+
+    /// Whether the entity passes every filter
+    pub fn matches(&self, entity: &EntityData) -> bool {
+        let reach = self.reach();
+        self.check(entity, reach.as_ref())
+    }
+
+    /// Every entity that passes every filter, in id order for searches over all entities and in
+    /// the parent's order for searches among children
+    pub fn run<'b>(&self, arena: &'b Arena) -> &'b [&'a EntityData] {
+        let reach = self.reach();
+        arena.alloc_iter(
+            self.candidates()
+                .filter(|entity| self.check(entity, reach.as_ref())),
+        )
+    }
+
+    /// How many entities pass every filter
+    pub fn count(&self) -> usize {
+        let reach = self.reach();
+        self.candidates()
+            .filter(|entity| self.check(entity, reach.as_ref()))
+            .count()
+    }
+
+    /// Whether any entity passes every filter
+    pub fn any(&self) -> bool {
+        let reach = self.reach();
+        self.candidates()
+            .any(|entity| self.check(entity, reach.as_ref()))
+    }
+
+    /// The entities worth checking: the children searched among, or else those linking to
+    /// the target of a link searched for, or else those in the smallest index by flag or kind
+    /// that covers the search, or else all of them
+    fn candidates(&self) -> Box<dyn Iterator<Item = &'a EntityData> + 'a> {
+        let entities = &self.sim.entities;
+        let ids: &[EntityId] = if let Some((rel, parent)) = self.children_of {
+            entities[parent].hierarchies.children(rel)
+        } else if let Some(&(link, target)) = self.links.first() {
+            return Box::new(entities.sources(target, link).map(move |id| &entities[id]));
+        } else if let Some(ids) = self
+            .flags
+            .iter()
+            .map(|&flag| entities.with_flag(flag))
+            .chain(self.kind.map(|kind| entities.of_kind(kind)))
+            .min_by_key(|ids| ids.len())
+        {
+            ids
+        } else if !self.any_flags.is_empty() {
+            let mut ids: Vec<EntityId> = self
+                .any_flags
+                .iter()
+                .flat_map(|&flag| entities.with_flag(flag))
+                .copied()
+                .collect();
+            ids.sort();
+            ids.dedup();
+            return Box::new(ids.into_iter().map(move |id| &entities[id]));
+        } else {
+            return Box::new(entities.iter());
+        };
+        Box::new(ids.iter().map(move |&id| &entities[id]))
+    }
+
+    fn check(&self, entity: &EntityData, reach: Option<&SecondaryMap<SiteId, ()>>) -> bool {
+        let entities = &self.sim.entities;
+        if let Some((rel, parent)) = self.children_of
+            && entity.hierarchies.parent(rel) != parent
+        {
+            return false;
+        }
+        if !entity.flags.check_all(self.flags) {
+            return false;
+        }
+        if !self.any_flags.is_empty() && !self.any_flags.iter().any(|&f| entity.flags.get(f)) {
+            return false;
+        }
+        if self.kind.is_some_and(|kind| kind != entity.kind_name) {
+            return false;
+        }
+        if self.mobile && entity.movement.is_none() {
+            return false;
+        }
+        if !self
+            .links
+            .iter()
+            .all(|&(link, target)| entity.links.all(link).any(|to| to == target))
+        {
+            return false;
+        }
+        if !self
+            .below
+            .iter()
+            .all(|&(rel, ancestor)| entities.is_descendant(rel, entity.id, ancestor))
+        {
+            return false;
+        }
+        if !self
+            .aspects
+            .iter()
+            .all(|&(aspect, at_least)| entity.aspects.get(aspect) >= at_least)
+        {
+            return false;
+        }
+        if let Some(reach) = reach
+            && !reach.contains_key(site_of(self.sim, entity.id))
+        {
+            return false;
+        }
+        true
+    }
+
+    /// The sites within reach, if the search is limited to those
+    fn reach(&self) -> Option<SecondaryMap<SiteId, ()>> {
+        let (from, steps) = self.near?;
+        let mut reach = SecondaryMap::new();
+        if from.is_null() {
+            return Some(reach);
+        }
+        let mut queue = VecDeque::from([(from, 0)]);
+        reach.insert(from, ());
+
+        while let Some((site, taken)) = queue.pop_front() {
+            if taken == steps {
+                continue;
+            }
+            for neighbour in self.sim.sites.graph.neighbours(site) {
+                if reach.insert(neighbour.id, ()).is_none() {
+                    queue.push_back((neighbour.id, taken + 1));
+                }
+            }
+        }
+        Some(reach)
+    }
+}
+
+/// Where an entity is on the map. Those on the road count as being at the last site they
+/// passed, and factions as being at their capital
+pub(crate) fn site_of(sim: &Simulation, entity: EntityId) -> SiteId {
+    let data = &sim.entities[entity];
+    if !data.bound_site.is_null() {
+        return data.bound_site;
+    }
+    if let Some(movement) = &data.movement {
+        return movement.site;
+    }
+    [HierarchyName::PlaceOf, HierarchyName::Capital]
+        .into_iter()
+        .map(|rel| data.hierarchies.parent(rel))
+        .find(|parent| !parent.is_null())
+        .map(|parent| sim.entities[parent].bound_site)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(entities: &[&EntityData]) -> Vec<EntityId> {
+        entities.iter().map(|entity| entity.id).collect()
+    }
+
+    /// Every entity passing the filter, found by going through them all
+    fn expected(sim: &Simulation, filter: impl Fn(&EntityData) -> bool) -> Vec<EntityId> {
+        sim.entities
+            .iter()
+            .filter(|&entity| filter(entity))
+            .map(|entity| entity.id)
+            .collect()
+    }
+
+    /// Checks every way of running the query against the expected results
+    fn assert_finds(query: Query, arena: &Arena, expected: &[EntityId]) {
+        assert_eq!(ids(query.run(arena)), expected);
+        assert_eq!(query.count(), expected.len());
+        assert_eq!(query.any(), !expected.is_empty());
+    }
+
+    #[test]
+    fn queries_find_what_a_full_search_does() {
+        let arena = Arena::default();
+        let sim = Simulation::new(9, &arena);
+        let entities = &sim.entities;
+        let rheged = entities.lookup("rheged");
+        let caer = entities.lookup("caer_ligualid");
+
+        let members = expected(&sim, |entity| {
+            entity.flags.get(Flag::IsPerson)
+                && entities.is_descendant(HierarchyName::Faction, entity.id, rheged)
+        });
+        assert!(!members.is_empty());
+        let query = Query::new(&sim)
+            .with_flags(&[Flag::IsPerson])
+            .below(HierarchyName::Faction, rheged);
+        assert_finds(query, &arena, &members);
+
+        let on_map = expected(&sim, |entity| {
+            entity.flags.get(Flag::IsLocation) || entity.flags.get(Flag::IsParty)
+        });
+        let query = Query::new(&sim).with_any_flag(&[Flag::IsLocation, Flag::IsParty]);
+        assert_finds(query, &arena, &on_map);
+
+        let ruler = entities[rheged].links.get(LinkName::Ruler);
+        let ruled = expected(&sim, |entity| entity.links.get(LinkName::Ruler) == ruler);
+        assert!(ruled.contains(&rheged));
+        let query = Query::new(&sim).linked_to(LinkName::Ruler, ruler);
+        assert_finds(query, &arena, &ruled);
+
+        let travellers: Vec<EntityId> = entities[caer]
+            .hierarchies
+            .children(HierarchyName::PlaceOf)
+            .iter()
+            .copied()
+            .filter(|&id| entities[id].movement.is_some())
+            .collect();
+        assert!(!travellers.is_empty());
+        let query = Query::new(&sim)
+            .children_of(HierarchyName::PlaceOf, caer)
+            .mobile();
+        assert_finds(query, &arena, &travellers);
+
+        let query = Query::new(&sim)
+            .with_flags(&[Flag::IsLocation])
+            .within(entities[caer].bound_site, 0);
+        assert_finds(query, &arena, &[caer]);
+
+        let query = Query::new(&sim).within(SiteId::null(), 3);
+        assert_finds(query, &arena, &[]);
+    }
+}
@@ -0,0 +1,351 @@
+use std::fmt;
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+use slotmap::{Key, KeyData};
+use util::arena::Arena;
+
+use crate::object::*;
+use crate::scenario::ScenarioErrors;
+use crate::simulation::Simulation;
+use crate::tick::TickRequest;
+
+/// Bumped whenever the layout of `CommandLog` changes
+const LOG_VERSION: u32 = 1;
+
+/// Everything needed to play a session back: how the simulation was created, and the commands
+/// of every tick since then, each with a checksum of the state it led to
+#[derive(Serialize, Deserialize)]
+pub struct CommandLog {
+    version: u32,
+    seed: u64,
+    scenario: String,
+    /// Checksum of the state right after creation
+    initial_checksum: u64,
+    ticks: Vec<LoggedTick>,
+    /// Ticks without commands since the last logged one
+    idle: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LoggedTick {
+    /// Ticks without commands that came before this one. These still advance some state (such as
+    /// colours propagating down hierarchies), so they have to be replayed too
+    idle_before: u32,
+    /// Turn number the tick was applied on
+    turn: usize,
+    end_turn: bool,
+    make_active: Option<LoggedObject>,
+    interacted_with_object: Option<LoggedObject>,
+    /// Checksum of the state after the tick
+    checksum: u64,
+}
+
+/// An `ObjectId` as stored in a log. The replay recreates the world from the same seed and
+/// commands, so keys come out the same and can be stored as they are
+#[derive(Serialize, Deserialize, Clone, Copy)]
+enum LoggedObject {
+    Null,
+    Global,
+    Site(u64),
+    Entity(u64),
+    AvailableAction(usize),
+}
+
+impl From<ObjectId> for LoggedObject {
+    fn from(value: ObjectId) -> Self {
+        match value.0 {
+            ObjectHandle::Null => Self::Null,
+            ObjectHandle::Global => Self::Global,
+            ObjectHandle::Site(id) => Self::Site(id.data().as_ffi()),
+            ObjectHandle::Entity(id) => Self::Entity(id.data().as_ffi()),
+            ObjectHandle::AvailableAction(idx) => Self::AvailableAction(idx),
+        }
+    }
+}
+
+impl From<LoggedObject> for ObjectId {
+    fn from(value: LoggedObject) -> Self {
+        ObjectId(match value {
+            LoggedObject::Null => ObjectHandle::Null,
+            LoggedObject::Global => ObjectHandle::Global,
+            LoggedObject::Site(key) => ObjectHandle::Site(KeyData::from_ffi(key).into()),
+            LoggedObject::Entity(key) => ObjectHandle::Entity(KeyData::from_ffi(key).into()),
+            LoggedObject::AvailableAction(idx) => ObjectHandle::AvailableAction(idx),
+        })
+    }
+}
+
+/// The parts of a `TickRequest` that affect the state of the simulation
+pub(crate) struct Command {
+    end_turn: bool,
+    make_active: Option<ObjectId>,
+    interacted_with_object: Option<ObjectId>,
+}
+
+impl Command {
+    pub fn of(request: &TickRequest) -> Self {
+        Self {
+            end_turn: request.end_turn,
+            make_active: request.make_active,
+            interacted_with_object: request.interacted_with_object,
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        !self.end_turn && self.make_active.is_none() && self.interacted_with_object.is_none()
+    }
+}
+
+impl CommandLog {
+    pub(crate) fn new(sim: &Simulation, scenario: &str) -> Self {
+        Self {
+            version: LOG_VERSION,
+            seed: sim.seed(),
+            scenario: scenario.to_string(),
+            initial_checksum: checksum(sim),
+            ticks: vec![],
+            idle: 0,
+        }
+    }
+
+    /// Logs a tick, given the commands it was requested with and the state it resulted in
+    pub(crate) fn record(&mut self, command: Command, turn: usize, sim: &Simulation) {
+        if command.is_idle() {
+            self.idle += 1;
+            return;
+        }
+
+        self.ticks.push(LoggedTick {
+            idle_before: std::mem::take(&mut self.idle),
+            turn,
+            end_turn: command.end_turn,
+            make_active: command.make_active.map(LoggedObject::from),
+            interacted_with_object: command.interacted_with_object.map(LoggedObject::from),
+            checksum: checksum(sim),
+        });
+    }
+
+    /// Number of ticks with commands in the log
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    pub fn save(&self, out: &mut impl Write) -> Result<(), ReplayError> {
+        ron::ser::to_writer(out, self).map_err(|err| ReplayError::Format(err.to_string()))
+    }
+
+    pub fn load(mut input: impl Read) -> Result<CommandLog, ReplayError> {
+        let mut text = String::new();
+        input
+            .read_to_string(&mut text)
+            .map_err(|err| ReplayError::Format(err.to_string()))?;
+
+        let log: CommandLog =
+            ron::from_str(&text).map_err(|err| ReplayError::Format(err.to_string()))?;
+        if log.version != LOG_VERSION {
+            return Err(ReplayError::UnsupportedVersion(log.version));
+        }
+        Ok(log)
+    }
+
+    /// Rebuilds the simulation from its seed and plays every logged tick back onto it, stopping
+    /// at the first tick whose resulting state differs from the recorded one
+    pub fn replay(&self, arena: &mut Arena) -> Result<Simulation, ReplayError> {
+        let mut sim = Simulation::from_scenario(&self.scenario, self.seed, arena)
+            .map_err(ReplayError::Scenario)?;
+        arena.reset();
+
+        let found = checksum(&sim);
+        if found != self.initial_checksum {
+            return Err(ReplayError::Diverged(Divergence {
+                tick: None,
+                turn: sim.turn_number,
+                expected: self.initial_checksum,
+                found,
+            }));
+        }
+
+        for (idx, logged) in self.ticks.iter().enumerate() {
+            idle_ticks(&mut sim, arena, logged.idle_before);
+
+            let request = TickRequest {
+                end_turn: logged.end_turn,
+                make_active: logged.make_active.map(ObjectId::from),
+                interacted_with_object: logged.interacted_with_object.map(ObjectId::from),
+                ..Default::default()
+            };
+            sim.tick(request, arena);
+            arena.reset();
+
+            let found = checksum(&sim);
+            if found != logged.checksum {
+                return Err(ReplayError::Diverged(Divergence {
+                    tick: Some(idx),
+                    turn: logged.turn,
+                    expected: logged.checksum,
+                    found,
+                }));
+            }
+        }
+
+        idle_ticks(&mut sim, arena, self.idle);
+        Ok(sim)
+    }
+}
+
+fn idle_ticks(sim: &mut Simulation, arena: &mut Arena, count: u32) {
+    for _ in 0..count {
+        sim.tick(TickRequest::default(), arena);
+        arena.reset();
+    }
+}
+
+/// Where a replay first stopped matching its log
+#[derive(Debug)]
+pub struct Divergence {
+    /// Index of the first logged tick whose outcome differs, or `None` if the freshly created
+    /// simulation already differs
+    pub tick: Option<usize>,
+    pub turn: usize,
+    pub expected: u64,
+    pub found: u64,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The log could not be encoded or decoded
+    Format(String),
+    UnsupportedVersion(u32),
+    /// The scenario stored in the log can no longer be loaded
+    Scenario(ScenarioErrors),
+    Diverged(Divergence),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Format(msg) => write!(f, "malformed command log: {msg}"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported command log version {version} (expected {LOG_VERSION})"
+            ),
+            Self::Scenario(errors) => write!(f, "invalid scenario:\n{errors}"),
+            Self::Diverged(div) => {
+                match div.tick {
+                    Some(tick) => write!(f, "replay diverged at logged tick {tick}")?,
+                    None => write!(f, "replay diverged on creation")?,
+                }
+                write!(
+                    f,
+                    " (turn {}, expected checksum {:016x}, found {:016x})",
+                    div.turn, div.expected, div.found
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// Hash of the whole state of a simulation, as captured by its save data
+pub(crate) fn checksum(sim: &Simulation) -> u64 {
+    let mut hasher = Fnv1a::default();
+    crate::save::save(sim, &mut hasher).expect("hashing does not fail");
+    hasher.0
+}
+
+/// FNV-1a, chosen over `DefaultHasher` because its output is fixed across Rust releases
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Write for Fnv1a {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for &byte in buf {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario;
+
+    /// Plays a short session on the built-in scenario: a selection, an idle tick, and some turns
+    fn record_session(arena: &mut Arena) -> (Simulation, CommandLog) {
+        let mut sim = Simulation::new(5, arena);
+        arena.reset();
+        sim.start_recording(scenario::DEFAULT);
+
+        let select = TickRequest {
+            interacted_with_object: Some(sim.lookup("caer_ligualid")),
+            ..Default::default()
+        };
+        sim.tick(select, arena);
+        sim.tick(TickRequest::default(), arena);
+        for _ in 0..5 {
+            let request = TickRequest {
+                end_turn: true,
+                ..Default::default()
+            };
+            sim.tick(request, arena);
+            arena.reset();
+        }
+        let log = sim.stop_recording().unwrap();
+        (sim, log)
+    }
+
+    #[test]
+    fn replay_reaches_the_recorded_state() {
+        let mut arena = Arena::default();
+        let (sim, log) = record_session(&mut arena);
+        assert_eq!(log.len(), 6);
+
+        let replayed = log.replay(&mut arena).unwrap();
+        assert_eq!(checksum(&replayed), checksum(&sim));
+    }
+
+    #[test]
+    fn replay_reports_the_first_tick_that_diverges() {
+        let mut arena = Arena::default();
+        let (_, mut log) = record_session(&mut arena);
+        log.ticks[3].checksum ^= 1;
+
+        match log.replay(&mut arena) {
+            Err(ReplayError::Diverged(div)) => {
+                assert_eq!(div.tick, Some(3));
+                assert_eq!(div.turn, log.ticks[3].turn);
+                assert_eq!(div.expected, log.ticks[3].checksum);
+            }
+            _ => panic!("the replay should have diverged"),
+        }
+    }
+
+    #[test]
+    fn replay_from_another_seed_diverges_on_creation() {
+        let mut arena = Arena::default();
+        let (_, mut log) = record_session(&mut arena);
+        log.seed += 1;
+
+        match log.replay(&mut arena) {
+            Err(ReplayError::Diverged(div)) => assert_eq!(div.tick, None),
+            _ => panic!("the replay should have diverged"),
+        }
+    }
+}
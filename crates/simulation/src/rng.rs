@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+
+use rand::SeedableRng;
+use rand::rngs::SmallRng;
+use serde::{Deserialize, Serialize};
+use strum::{EnumCount, EnumIter};
+
+/// Independent sources of randomness besides those of the systems, which each have a stream of
+/// their own. Every subsystem draws from its own stream, so that adding or removing random
+/// draws in one of them does not perturb what the others see
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Debug,
+    EnumIter,
+    EnumCount,
+    Serialize,
+    Deserialize,
+)]
+pub(crate) enum RngStream {
+    /// World creation out of a scenario
+    Init,
+    /// Outcomes of the actions the player performs
+    Interaction,
+}
+
+/// The random state of a simulation: the world seed, plus how far along each stream is
+#[derive(Default)]
+pub(crate) struct Rngs {
+    seed: u64,
+    draws: [u64; RngStream::COUNT],
+    /// Draws from the stream of each end of turn system, by the name it was registered under
+    system_draws: BTreeMap<&'static str, u64>,
+}
+
+impl Rngs {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            draws: Default::default(),
+            system_draws: BTreeMap::new(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// How many generators have been handed out by the stream so far
+    pub fn draws(&self, stream: RngStream) -> u64 {
+        self.draws[stream as usize]
+    }
+
+    pub fn set_draws(&mut self, stream: RngStream, draws: u64) {
+        self.draws[stream as usize] = draws;
+    }
+
+    /// Draws from the stream of every system that used it so far
+    pub fn system_draws(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        self.system_draws
+            .iter()
+            .map(|(&name, &draws)| (name, draws))
+    }
+
+    pub fn set_system_draws(&mut self, system: &'static str, draws: u64) {
+        self.system_draws.insert(system, draws);
+    }
+
+    /// Hands out a generator for the stream. Each call advances the stream, so two calls never
+    /// produce the same sequence, and the outcome only depends on the seed and on how many
+    /// times the stream was used before
+    pub fn stream(&mut self, stream: RngStream) -> SmallRng {
+        let draws = &mut self.draws[stream as usize];
+        generator(self.seed, stream as u64, draws)
+    }
+
+    /// Like `stream`, for the stream of the system registered under the name
+    pub fn system_stream(&mut self, system: &'static str) -> SmallRng {
+        let draws = self.system_draws.entry(system).or_default();
+        generator(self.seed, hash_name(system), draws)
+    }
+}
+
+/// The seed is mixed on its own first, so that neighbouring seeds do not share streams
+fn generator(seed: u64, stream: u64, draws: &mut u64) -> SmallRng {
+    let seed = mix(mix(mix(seed) ^ stream) ^ *draws);
+    *draws += 1;
+    SmallRng::seed_from_u64(seed)
+}
+
+/// Stands in for the name of a system, the same across runs and builds
+fn hash_name(name: &str) -> u64 {
+    name.bytes()
+        .fold(mix(RngStream::COUNT as u64), |acc, byte| {
+            mix(acc ^ byte as u64)
+        })
+}
+
+/// SplitMix64 finaliser, spreads nearby inputs far apart
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    fn first_draw(mut rng: SmallRng) -> u64 {
+        rng.r#gen()
+    }
+
+    #[test]
+    fn neighbouring_seeds_do_not_share_streams() {
+        let init = first_draw(Rngs::new(2705).stream(RngStream::Init));
+        let interaction = first_draw(Rngs::new(2704).stream(RngStream::Interaction));
+        assert_ne!(init, interaction);
+    }
+
+    #[test]
+    fn systems_draw_apart_from_each_other() {
+        let mut busy = Rngs::new(1);
+        for _ in 0..5 {
+            busy.system_stream("movement");
+        }
+        let mut idle = Rngs::new(1);
+        assert_eq!(
+            first_draw(busy.system_stream("ageing")),
+            first_draw(idle.system_stream("ageing"))
+        );
+        assert_ne!(
+            first_draw(idle.system_stream("movement")),
+            first_draw(idle.system_stream("ageing"))
+        );
+    }
+}
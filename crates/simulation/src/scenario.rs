@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::actions::{Kinship, Role};
+use crate::entities::HierarchyName;
+use crate::succession::SuccessionLaw;
+
+/// The scenario the game ships with
+pub(crate) const DEFAULT: &str = include_str!("../../../assets/scenarios/rheged.toml");
+
+/// A reference to some other scenario entry, remembering where it was written
+pub(crate) type Tag = Spanned<String>;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Scenario {
+    #[serde(default)]
+    pub aspects: Vec<AspectDesc>,
+    #[serde(default)]
+    pub religions: Vec<ReligionDesc>,
+    #[serde(default)]
+    pub cultures: Vec<CultureDesc>,
+    #[serde(default)]
+    pub prototypes: Vec<PrototypeDesc>,
+    #[serde(default)]
+    pub sites: Vec<SiteDesc>,
+    #[serde(default)]
+    pub factions: Vec<FactionDesc>,
+    #[serde(default)]
+    pub location_kinds: Vec<LocationKindDesc>,
+    #[serde(default)]
+    pub locations: Vec<LocationDesc>,
+    #[serde(default)]
+    pub cards: Vec<CardDesc>,
+    #[serde(default)]
+    pub actions: Vec<ActionDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AspectDesc {
+    pub tag: Tag,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ReligionDesc {
+    pub tag: Tag,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CultureDesc {
+    pub tag: Tag,
+    pub name: String,
+    /// Faith kept by the locations of the culture, unless they say otherwise
+    pub religion: Option<Tag>,
+    /// One of the name lists built into the game, for men
+    pub name_list: Option<Tag>,
+    /// Extra names for men, on top of those in `name_list`
+    #[serde(default)]
+    pub names: Vec<String>,
+    /// Built-in name lists for women, epithets and places
+    pub female_name_list: Option<Tag>,
+    pub epithet_list: Option<Tag>,
+    pub place_name_list: Option<Tag>,
+    /// How people are named after their father, with `{}` standing in for his name
+    pub son_of: Option<String>,
+    pub daughter_of: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct PrototypeDesc {
+    pub tag: Tag,
+    pub name: String,
+    pub kind: String,
+    #[serde(default)]
+    pub flags: Vec<Tag>,
+    #[serde(default)]
+    pub has_location: bool,
+    #[serde(default)]
+    pub has_faction: bool,
+    #[serde(default)]
+    pub aspects: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub sprite: String,
+    /// Distance entities of this prototype travel in a turn; those without one stay put
+    pub speed: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SiteDesc {
+    pub tag: Tag,
+    pub pos: (f32, f32),
+    /// Sites this one is connected to. Connections are two-way, so they only
+    /// need to be listed on one of the two ends
+    #[serde(default)]
+    pub connections: Vec<Tag>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FactionDesc {
+    pub tag: Tag,
+    pub name: String,
+    pub parent: Option<Tag>,
+    /// Picked at random when missing
+    pub color: Option<(u8, u8, u8)>,
+    #[serde(default)]
+    pub succession: SuccessionLaw,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LocationKindDesc {
+    pub tag: Tag,
+    pub name: String,
+    pub sprite: String,
+    pub size: f32,
+    /// How many notable people to create in each location of this kind
+    pub people: usize,
+    /// How many commoners each location of this kind starts out with
+    #[serde(default)]
+    pub population: f64,
+    /// Population at which growth stops
+    #[serde(default)]
+    pub capacity: f64,
+    /// Goods made each turn, per head
+    #[serde(default)]
+    pub production: BTreeMap<String, f64>,
+    /// Goods used up each turn, per head
+    #[serde(default)]
+    pub consumption: BTreeMap<String, f64>,
+    /// Goods each location of this kind starts out with
+    #[serde(default)]
+    pub stockpile: BTreeMap<String, f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LocationDesc {
+    /// Made up in the fashion of the location's culture if left out
+    pub name: Option<String>,
+    pub site: Tag,
+    pub culture: Tag,
+    /// Faith kept here, instead of that of the location's culture
+    pub religion: Option<Tag>,
+    pub kind: Tag,
+    pub faction: Tag,
+    /// Commoners by culture tag, instead of the usual number for the kind, all of the
+    /// location's culture
+    pub population: Option<BTreeMap<Tag, f64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CardDesc {
+    pub prototype: Tag,
+    pub location: Tag,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ActionDesc {
+    pub tag: Tag,
+    pub name: String,
+    /// What the entity taking the action must be
+    #[serde(default)]
+    pub subject: RequirementDesc,
+    /// What the entity the action is taken towards must be
+    #[serde(default)]
+    pub target: RequirementDesc,
+    /// How the target must stand to the subject in the hierarchies
+    #[serde(default)]
+    pub relations: Vec<RelationDesc>,
+    /// Most steps along the site graph between the subject and the target, if any
+    pub max_distance: Option<usize>,
+    /// Goods taken from the stockpile of the place the subject is at
+    #[serde(default)]
+    pub cost: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub effects: Vec<EffectDesc>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RequirementDesc {
+    #[serde(default)]
+    pub flags: Vec<Tag>,
+    pub kind: Option<String>,
+    /// Whether the entity must be able to travel
+    #[serde(default)]
+    pub travels: bool,
+    /// Least amount of each aspect the entity must have
+    #[serde(default)]
+    pub aspects: BTreeMap<String, f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RelationDesc {
+    pub hierarchy: HierarchyName,
+    pub kinship: Kinship,
+}
+
+/// Effects are reported against the tag of their action, as the lines they are written on are
+/// lost while telling them apart
+#[derive(Deserialize)]
+#[serde(tag = "effect", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum EffectDesc {
+    Spawn {
+        prototype: String,
+        at: Role,
+    },
+    SetParent {
+        hierarchy: HierarchyName,
+        child: Role,
+        parent: Role,
+    },
+    Travel,
+    Stockpile {
+        of: Role,
+        good: String,
+        amount: f64,
+    },
+    Relation {
+        amount: f64,
+    },
+}
+
+pub(crate) fn parse(source: &str) -> Result<Scenario, ScenarioErrors> {
+    toml::from_str(source).map_err(|err| {
+        let line = err
+            .span()
+            .map(|span| line_of(source, span.start))
+            .unwrap_or_default();
+        let error = ScenarioError {
+            line,
+            message: err.message().to_string(),
+        };
+        ScenarioErrors(vec![error])
+    })
+}
+
+fn line_of(source: &str, offset: usize) -> usize {
+    let offset = offset.min(source.len());
+    source.as_bytes()[..offset]
+        .iter()
+        .filter(|&&c| c == b'\n')
+        .count()
+        + 1
+}
+
+/// Collects the problems found while building a simulation out of a scenario
+pub(crate) struct Report<'a> {
+    source: &'a str,
+    errors: Vec<ScenarioError>,
+}
+
+impl<'a> Report<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            errors: vec![],
+        }
+    }
+
+    pub fn error<T>(&mut self, at: &Spanned<T>, message: impl Into<String>) {
+        self.errors.push(ScenarioError {
+            line: line_of(self.source, at.span().start),
+            message: message.into(),
+        });
+    }
+
+    pub fn finish(mut self) -> Result<(), ScenarioErrors> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        self.errors.sort_by_key(|err| err.line);
+        Err(ScenarioErrors(self.errors))
+    }
+}
+
+#[derive(Debug)]
+pub struct ScenarioError {
+    /// 1-based line of the scenario source the error refers to
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct ScenarioErrors(pub Vec<ScenarioError>);
+
+impl fmt::Display for ScenarioErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, error) in self.0.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ScenarioErrors {}
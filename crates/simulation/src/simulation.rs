@@ -1,32 +1,139 @@
+use slotmap::Key;
 use util::arena::*;
 
+use crate::actions::ActionDef;
+use crate::ai::Deliberation;
 use crate::aspects::Aspects;
+use crate::diplomacy::{Diplomacy, DiplomaticAction};
 use crate::entities::{Entities, EntityId};
+use crate::events::{Event, EventKind, Events};
+use crate::object::ObjectId;
+use crate::replay::{Command, CommandLog};
+use crate::rng::Rngs;
+use crate::save::SaveError;
+use crate::scenario::{self, ScenarioErrors};
 use crate::sites::*;
 use crate::spawn::*;
+use crate::summary::Summary;
+use crate::systems::Systems;
 use crate::tick::TickRequest;
+use crate::vassals::Realignment;
 
 #[derive(Default)]
 pub struct Simulation {
     pub(crate) turn_number: usize,
+    pub(crate) rngs: Rngs,
     pub(crate) aspects: Aspects,
     pub(crate) sites: Sites,
     pub(crate) prototypes: Prototypes,
+    /// Everything entities can do towards each other, besides diplomacy
+    pub(crate) actions: Vec<ActionDef>,
     pub(crate) entities: Entities,
     pub(crate) interaction: Interaction,
     pub(crate) active_agent: EntityId,
+    pub(crate) events: Events,
+    pub(crate) diplomacy: Diplomacy,
+    /// What the AI agents weighed up on the last turn
+    pub(crate) deliberations: Vec<Deliberation>,
+    /// Entities to despawn at the next safe point, see `spawn::despawn`
+    pub(crate) despawning: Vec<EntityId>,
+    pub(crate) recording: Option<CommandLog>,
+    pub(crate) systems: Systems,
 }
 
 impl Simulation {
-    pub fn new(arena: &Arena) -> Simulation {
-        let mut sim = Simulation::default();
+    /// Creates a simulation out of the scenario built into the game. Everything random that
+    /// happens in the simulation follows from the world seed
+    pub fn new(seed: u64, arena: &Arena) -> Simulation {
+        Self::from_scenario(scenario::DEFAULT, seed, arena).expect("the built-in scenario is valid")
+    }
+
+    /// Creates a simulation out of the source text of a scenario file, reporting every
+    /// problem found along with the line it was found at
+    pub fn from_scenario(
+        source: &str,
+        seed: u64,
+        arena: &Arena,
+    ) -> Result<Simulation, ScenarioErrors> {
+        let scenario = scenario::parse(source)?;
+        let mut report = scenario::Report::new(source);
+
+        let mut sim = Simulation {
+            rngs: Rngs::new(seed),
+            ..Default::default()
+        };
+        crate::init::init(&mut sim, arena, &scenario, &mut report);
+        report.finish()?;
+        Ok(sim)
+    }
+
+    pub fn turn_number(&self) -> usize {
+        self.turn_number
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.rngs.seed()
+    }
+
+    /// Finds the entity with the given tag, returning a null object if there is none
+    pub fn lookup(&self, tag: &str) -> ObjectId {
+        let id = self.entities.lookup(tag);
+        if id.is_null() {
+            ObjectId::default()
+        } else {
+            ObjectId::entity(id)
+        }
+    }
+
+    /// Every option each AI agent scored on the last turn, for debugging
+    pub fn ai_report(&self) -> String {
+        crate::ai::report(self)
+    }
 
-        crate::init::init(&mut sim, arena, 2704);
-        sim
+    pub fn summary(&self) -> Summary {
+        crate::summary::summarize(self)
+    }
+
+    /// Writes the whole state of the simulation out, in a form `load` can read back
+    pub fn save(&self, out: &mut impl std::io::Write) -> Result<(), SaveError> {
+        crate::save::save(self, out)
+    }
+
+    pub fn load(input: impl std::io::Read) -> Result<Simulation, SaveError> {
+        crate::save::load(input)
+    }
+
+    /// Starts logging every tick from now on, so that the session can be replayed later. Meant
+    /// to be called right after creation, passing the source of the scenario that was used
+    pub fn start_recording(&mut self, scenario: &str) {
+        self.recording = Some(CommandLog::new(self, scenario));
+    }
+
+    pub fn stop_recording(&mut self) -> Option<CommandLog> {
+        self.recording.take()
+    }
+
+    /// Adds an event that happened on the current turn to the history
+    pub(crate) fn record_event(&mut self, kind: EventKind, text: String, subjects: Vec<EntityId>) {
+        self.events.record(Event {
+            turn: self.turn_number,
+            kind,
+            text,
+            subjects,
+        });
     }
 
     pub fn tick(&mut self, request: TickRequest, arena: &Arena) -> crate::view::SimView {
-        crate::tick::tick(self, request, arena)
+        let command = Command::of(&request);
+        let turn = self.turn_number;
+
+        let view = crate::tick::tick(self, request, arena);
+
+        if let Some(mut log) = self.recording.take() {
+            log.record(command, turn, self);
+            self.recording = Some(log);
+        }
+        view
     }
 }
 
@@ -39,7 +146,12 @@ pub(crate) struct Interaction {
 #[derive(Default)]
 pub(crate) struct Action {
     pub name: &'static str,
-    pub spawn_prototype: Option<(Prototype, PrototypeArgs)>,
+    /// An entry of the action catalog, taken by the first entity towards the second
+    pub catalog: Option<(usize, EntityId, EntityId)>,
+    /// Taken by the first realm towards the second
+    pub diplomacy: Option<(DiplomaticAction, EntityId, EntityId)>,
+    /// Taken by the first faction towards the second
+    pub realignment: Option<(Realignment, EntityId, EntityId)>,
 }
 
 pub(crate) struct AvailableActions {
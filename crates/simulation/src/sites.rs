@@ -32,16 +32,6 @@ impl Sites {
         id
     }
 
-    pub(crate) fn lookup_data_mut(&mut self, tag: &str) -> Option<&mut SiteData> {
-        match self.lookup(tag) {
-            Some(id) => Some(&mut self.data[id]),
-            None => {
-                println!("Undefined site '{}'", tag);
-                None
-            }
-        }
-    }
-
     pub(crate) fn pos_of(&self, site: SiteId) -> V2 {
         self.graph
             .get(site)
@@ -1,11 +1,14 @@
 use rand::rngs::SmallRng;
 use slotmap::Key;
 use util::arena::Arena;
-use util::tagged::TaggedCollection;
 use util::tagged::Tags;
 
 use crate::aspects::*;
 use crate::entities::*;
+use crate::events::EventKind;
+use crate::life::{Life, Sex};
+use crate::movement::Movement;
+use crate::names;
 use crate::simulation::*;
 use crate::sites::*;
 use crate::view::RGB;
@@ -26,16 +29,33 @@ impl Prototypes {
         self.tags.insert(tag, id);
         id
     }
+
+    pub fn lookup(&self, tag: &str) -> Option<&Prototype> {
+        self.tags.lookup(tag).map(|id| &self.entries[id.0])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Prototype)> {
+        self.entries.iter().enumerate().map(|(idx, proto)| {
+            let tag = self
+                .tags
+                .reverse_lookup(&PrototypeId(idx))
+                .unwrap_or_default();
+            (tag, proto)
+        })
+    }
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone)]
 pub(crate) struct Prototype {
     pub name: &'static str,
     pub kind: &'static str,
-    pub flags: &'static [Flag],
+    pub flags: Vec<Flag>,
     pub has_location: bool,
     pub has_faction: bool,
-    pub aspects: Option<&'static AspectVector>,
+    pub aspects: Option<AspectVector>,
+    pub sprite: &'static str,
+    /// Distance covered in a turn, for prototypes of entities that travel
+    pub speed: Option<f32>,
 }
 
 impl std::ops::Index<PrototypeId> for Prototypes {
@@ -46,17 +66,6 @@ impl std::ops::Index<PrototypeId> for Prototypes {
     }
 }
 
-impl TaggedCollection for Prototypes {
-    type Output = Prototype;
-
-    fn lookup(&self, tag: &str) -> Option<Self::Output> {
-        self.tags
-            .lookup(tag)
-            .and_then(|id| self.entries.get(id.0))
-            .copied()
-    }
-}
-
 #[derive(Default)]
 pub(crate) struct PrototypeArgs {
     pub tag: &'static str,
@@ -66,7 +75,7 @@ pub(crate) struct PrototypeArgs {
 
 impl Prototype {
     pub fn spawn(
-        self,
+        &self,
         sim: &mut Simulation,
         arena: &Arena,
         rng: &mut SmallRng,
@@ -88,13 +97,24 @@ impl Prototype {
             tag: args.tag,
             name: Name::Fixed(self.name),
             kind: self.kind,
-            looks: Looks::default(),
+            looks: Looks {
+                sprite: self.sprite,
+                size: 1.,
+                color: if self.has_faction {
+                    Color::Dynamic
+                } else {
+                    Color::default()
+                },
+            },
             site: SiteId::null(),
-            flags: self.flags,
+            flags: &self.flags,
             links: &[],
             parents: parents.into_bump_slice(),
             children: &[],
-            aspects: self.aspects,
+            aspects: self.aspects.as_ref(),
+            speed: self.speed,
+            age: None,
+            sex: Sex::default(),
         };
 
         spawn_entity(sim, spawn, rng)
@@ -113,6 +133,12 @@ pub(crate) struct SpawnEntity<'a> {
     pub parents: &'a [(HierarchyName, EntityId)],
     pub children: &'a [(HierarchyName, EntityId)],
     pub aspects: Option<&'a AspectVector>,
+    /// Makes the entity able to travel, starting from its site or the site of its place
+    pub speed: Option<f32>,
+    /// Makes the entity mortal, starting out this many years old
+    pub age: Option<f64>,
+    /// Sex of a mortal entity
+    pub sex: Sex,
 }
 
 impl SpawnEntity<'_> {
@@ -123,7 +149,12 @@ impl SpawnEntity<'_> {
 
 pub(crate) enum Name<'a> {
     Fixed(&'a str),
-    FromList(EntityId, NameList),
+    /// Made up in the fashion of the culture, after the father if there is one, see
+    /// `names::person_name`
+    Person {
+        culture: EntityId,
+        father: EntityId,
+    },
 }
 
 impl Default for Name<'_> {
@@ -153,17 +184,11 @@ impl Default for Color {
 fn spawn_entity(sim: &mut Simulation, info: SpawnEntity, rng: &mut SmallRng) -> EntityId {
     let name = match info.name {
         Name::Fixed(x) => x.to_string(),
-        Name::FromList(source, list) => sim.entities[source]
-            .name_lists
-            .as_ref()
-            .unwrap()
-            .pick_randomly(list, rng)
-            .to_string(),
+        Name::Person { culture, father } => names::person_name(sim, culture, info.sex, father, rng),
     };
 
     let entity = sim.entities.spawn_with_tag(info.tag);
     entity.name = name;
-    entity.kind_name = info.kind;
 
     entity.sprite = info.looks.sprite;
     entity.size = info.looks.size;
@@ -171,19 +196,15 @@ fn spawn_entity(sim: &mut Simulation, info: SpawnEntity, rng: &mut SmallRng) ->
         Color::Fixed(rgb) => EntityColor {
             current: rgb,
             dirty: false,
+            dynamic: false,
         },
         Color::Dynamic => EntityColor {
             current: Default::default(),
             dirty: true,
+            dynamic: true,
         },
     };
 
-    entity.flags.set_all(info.flags, true);
-
-    for &(link, tgt) in info.links {
-        entity.links.set(link, tgt);
-    }
-
     if !info.site.is_null() {
         bind_entity_to_site(entity, &mut sim.sites.data[info.site]);
     }
@@ -191,18 +212,86 @@ fn spawn_entity(sim: &mut Simulation, info: SpawnEntity, rng: &mut SmallRng) ->
     entity.aspects = info.aspects.cloned().unwrap_or_default();
 
     let entity = entity.id;
+    sim.entities.set_kind(entity, info.kind);
+    sim.entities.set_flags(entity, info.flags, true);
+    for &(link, tgt) in info.links {
+        sim.entities.add_link(entity, link, tgt);
+    }
 
-    for &(rel, parent) in info.parents {
-        sim.entities.set_parent(rel, entity, parent);
+    // A new entity has no relatives yet, so it cannot end up as its own ancestor
+    for &(rel, parent) in info.parents.iter().filter(|(_, parent)| !parent.is_null()) {
+        sim.entities
+            .set_parent(rel, entity, parent)
+            .expect("parents of spawned entities exist");
     }
 
-    for &(rel, child) in info.children {
-        sim.entities.set_parent(rel, child, entity);
+    for &(rel, child) in info.children.iter().filter(|(_, child)| !child.is_null()) {
+        sim.entities
+            .set_parent(rel, child, entity)
+            .expect("children of spawned entities exist");
     }
 
+    if let Some(speed) = info.speed {
+        let place = sim.entities[entity]
+            .hierarchies
+            .parent(HierarchyName::PlaceOf);
+        let site = if info.site.is_null() {
+            sim.entities[place].bound_site
+        } else {
+            info.site
+        };
+        assert!(!site.is_null(), "travelling entities start out at a site");
+        sim.entities[entity].movement = Some(Box::new(Movement::new(site, speed)));
+    }
+
+    if let Some(age) = info.age {
+        sim.entities[entity].life = Some(Life::aged(sim.turn_number, age, info.sex));
+    }
+
+    let data = &sim.entities[entity];
+    let text = format!("{} ({}) came into the world", data.name, data.kind_name);
+    sim.record_event(EventKind::Spawned, text, vec![entity]);
+
     entity
 }
 
+/// Has the entity despawned at the next safe point, once the system or interaction at hand is
+/// done with it. Until then it stays around, but takes no part in any action
+pub(crate) fn despawn(sim: &mut Simulation, id: EntityId) {
+    if !sim.despawning.contains(&id) {
+        sim.despawning.push(id);
+    }
+}
+
+pub(crate) fn is_despawning(sim: &Simulation, id: EntityId) -> bool {
+    sim.despawning.contains(&id)
+}
+
+/// Despawns every entity queued up by `despawn`, in the order they were queued, leaving nothing
+/// pointing at them
+pub(crate) fn despawn_queued(sim: &mut Simulation) {
+    for id in std::mem::take(&mut sim.despawning) {
+        let Some(data) = sim.entities.get(id) else {
+            continue;
+        };
+        let text = format!("{} ({}) left the world", data.name, data.kind_name);
+        let site = data.bound_site;
+        sim.record_event(EventKind::Despawned, text, vec![id]);
+
+        if !site.is_null() {
+            sim.sites.data[site].bound_entity = EntityId::null();
+        }
+        if sim.active_agent == id {
+            sim.active_agent = EntityId::null();
+        }
+        if sim.interaction.selected_entity == id {
+            sim.interaction.selected_entity = EntityId::null();
+        }
+        sim.diplomacy.forget(id);
+        sim.entities.despawn(id);
+    }
+}
+
 #[inline]
 fn bind_entity_to_site(entity: &mut EntityData, site: &mut SiteData) {
     assert!(entity.bound_site.is_null());
@@ -0,0 +1,319 @@
+use rand::rngs::SmallRng;
+use serde::{Deserialize, Serialize};
+use slotmap::Key;
+use util::arena::Arena;
+
+use crate::entities::*;
+use crate::events::EventKind;
+use crate::simulation::Simulation;
+use crate::spawn::{self, SpawnEntity};
+use crate::vassals;
+
+/// Age, in years, from which people can rule and have a say in who does
+const ADULT_AGE: f64 = 16.;
+
+/// How a faction picks its next ruler
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SuccessionLaw {
+    /// The ablest adult of the ruler's dynasty takes over
+    #[default]
+    Tanistry,
+    /// The children of the ruler split the faction's locations between them, the eldest
+    /// keeping the faction itself and the others setting up factions of their own
+    PartibleInheritance,
+    /// The adult members of the faction each vote for the one most like themselves
+    Elective,
+}
+
+impl SuccessionLaw {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Tanistry => "Tanistry",
+            Self::PartibleInheritance => "Partible Inheritance",
+            Self::Elective => "Elective",
+        }
+    }
+}
+
+/// Has every faction without a ruler be ruled by its eldest member
+pub(crate) fn crown_eldest(sim: &mut Simulation) {
+    let factions: Vec<EntityId> = sim
+        .entities
+        .with_flag(Flag::IsFaction)
+        .iter()
+        .copied()
+        .filter(|&id| sim.entities[id].links.get(LinkName::Ruler).is_null())
+        .collect();
+
+    for faction in factions {
+        let eldest = members(sim, faction, EntityId::null())
+            .into_iter()
+            .min_by_key(|&id| sim.entities[id].life.map(|life| life.born));
+        if let Some(ruler) = eldest {
+            sim.entities.set_link(faction, LinkName::Ruler, ruler);
+        }
+    }
+}
+
+/// Has the members of every faction left without a ruler pick one among themselves, once
+/// there is anyone to pick
+pub(crate) fn fill_vacancies(sim: &mut Simulation, _: &Arena, _: &mut SmallRng) {
+    let vacant: Vec<EntityId> = sim
+        .entities
+        .with_flag(Flag::IsFaction)
+        .iter()
+        .copied()
+        .filter(|&id| sim.entities[id].links.get(LinkName::Ruler).is_null())
+        .collect();
+
+    for faction in vacant {
+        let Some(ruler) = elect(sim, faction, EntityId::null()) else {
+            continue;
+        };
+        sim.entities.set_link(faction, LinkName::Ruler, ruler);
+
+        let text = format!(
+            "{} was chosen to rule {}",
+            sim.entities[ruler].name, sim.entities[faction].name
+        );
+        sim.record_event(EventKind::Succession, text, vec![faction, ruler]);
+    }
+}
+
+/// Settles who follows a ruler about to die in every faction they rule, according to the
+/// law of each
+pub(crate) fn succeed(sim: &mut Simulation, ruler: EntityId, rng: &mut SmallRng) {
+    let factions: Vec<EntityId> = sim.entities.sources(ruler, LinkName::Ruler).collect();
+
+    for faction in factions {
+        let law = sim.entities[faction].succession.unwrap_or_default();
+        let heir = match law {
+            SuccessionLaw::Tanistry => tanist(sim, faction, ruler),
+            SuccessionLaw::PartibleInheritance => partition(sim, faction, ruler, rng),
+            SuccessionLaw::Elective => None,
+        };
+        // Where the law finds no one, the members choose among themselves
+        let heir = heir.or_else(|| elect(sim, faction, ruler));
+
+        let text = match heir {
+            Some(heir) => format!(
+                "{} succeeded {} as ruler of {}",
+                sim.entities[heir].name, sim.entities[ruler].name, sim.entities[faction].name
+            ),
+            None => format!(
+                "{} was left without a ruler after {}",
+                sim.entities[faction].name, sim.entities[ruler].name
+            ),
+        };
+        let heir = heir.unwrap_or_default();
+        sim.entities.set_link(faction, LinkName::Ruler, heir);
+
+        sim.record_event(EventKind::Succession, text, vec![faction, heir, ruler]);
+    }
+}
+
+/// Adult people belonging directly to the faction, other than the one given and those about to
+/// leave the world
+fn members(sim: &Simulation, faction: EntityId, except: EntityId) -> Vec<EntityId> {
+    sim.entities
+        .children_with_flags(
+            &sim.entities[faction],
+            HierarchyName::Faction,
+            &[Flag::IsPerson],
+        )
+        .filter(|entity| {
+            entity.id != except && is_adult(sim, entity) && !spawn::is_despawning(sim, entity.id)
+        })
+        .map(|entity| entity.id)
+        .collect()
+}
+
+fn is_adult(sim: &Simulation, entity: &EntityData) -> bool {
+    entity
+        .life
+        .is_some_and(|life| life.age(sim.turn_number) >= ADULT_AGE)
+}
+
+/// Whether the entity can take over from the ruler in the given realm: an adult of that realm,
+/// other than the ruler, who is not about to leave the world
+fn can_inherit(sim: &Simulation, entity: &EntityData, ruler: EntityId, realm: EntityId) -> bool {
+    entity.id != ruler
+        && is_adult(sim, entity)
+        && sim.entities.root_of(HierarchyName::Faction, entity.id) == realm
+        && !spawn::is_despawning(sim, entity.id)
+}
+
+/// The adult of the ruler's dynasty in the best health, the eldest if several are as fit
+fn tanist(sim: &Simulation, faction: EntityId, ruler: EntityId) -> Option<EntityId> {
+    let dynasty = sim.entities[ruler]
+        .hierarchies
+        .parent(HierarchyName::Dynasty);
+    if dynasty.is_null() {
+        return None;
+    }
+    let realm = sim.entities.root_of(HierarchyName::Faction, faction);
+
+    sim.entities
+        .children(HierarchyName::Dynasty, &sim.entities[dynasty])
+        .filter(|entity| can_inherit(sim, entity, ruler, realm))
+        .filter_map(|entity| Some((entity.id, entity.life?)))
+        .max_by(|(_, a), (_, b)| {
+            a.health
+                .total_cmp(&b.health)
+                .then_with(|| b.born.cmp(&a.born))
+        })
+        .map(|(id, _)| id)
+}
+
+/// Shares the locations of the faction out between the adult children of the ruler living in
+/// its realm, eldest first. The eldest keeps the faction, and every other child who gets a
+/// location sets up a faction of their own next to it, ruling from there
+fn partition(
+    sim: &mut Simulation,
+    faction: EntityId,
+    ruler: EntityId,
+    rng: &mut SmallRng,
+) -> Option<EntityId> {
+    let realm = sim.entities.root_of(HierarchyName::Faction, faction);
+    let mut heirs = crate::family::children(&sim.entities, ruler);
+    heirs.retain(|&child| can_inherit(sim, &sim.entities[child], ruler, realm));
+    heirs.sort_by_key(|&child| sim.entities[child].life.map(|life| life.born));
+    let (&eldest, younger) = heirs.split_first()?;
+
+    // The capital always stays with the faction
+    let seat = sim.entities[faction]
+        .hierarchies
+        .parent(HierarchyName::Capital);
+    let locations: Vec<EntityId> = sim
+        .entities
+        .children_with_flags(
+            &sim.entities[faction],
+            HierarchyName::Faction,
+            &[Flag::IsLocation],
+        )
+        .map(|entity| entity.id)
+        .filter(|&id| id != seat)
+        .collect();
+
+    let liege = sim.entities[faction]
+        .hierarchies
+        .parent(HierarchyName::Faction);
+    let law = sim.entities[faction].succession;
+    let shares = younger.len() + 1;
+
+    for (i, &heir) in younger.iter().enumerate() {
+        // The eldest already has the capital, so the others take the first shares and the
+        // eldest keeps whatever is left over
+        let share: Vec<EntityId> = locations.iter().skip(i).step_by(shares).copied().collect();
+        let Some(&capital) = share.first() else {
+            continue;
+        };
+
+        let name = sim.entities[capital].name.clone();
+        let parents = [
+            (HierarchyName::Faction, liege),
+            (HierarchyName::Capital, capital),
+        ];
+        let info = SpawnEntity {
+            name: spawn::Name::Fixed(&name),
+            kind: "Faction",
+            looks: spawn::Looks {
+                color: spawn::Color::Fixed(crate::init::random_color(rng)),
+                ..Default::default()
+            },
+            flags: &[Flag::IsFaction],
+            links: &[(LinkName::Ruler, heir)],
+            parents: &parents,
+            ..Default::default()
+        };
+        let split = info.spawn(sim, rng);
+        sim.entities[split].succession = law;
+
+        for &member in share.iter().chain([&heir]) {
+            sim.entities
+                .set_parent(HierarchyName::Faction, member, split)
+                .expect("a new faction is below none of its members");
+        }
+        vassals::mark_colours_dirty(&mut sim.entities, split);
+    }
+
+    Some(eldest)
+}
+
+/// Has every adult member of the faction vote for the one whose aspects are closest to their
+/// own, and returns whoever got the most votes, the eldest among those tied
+fn elect(sim: &Simulation, faction: EntityId, ruler: EntityId) -> Option<EntityId> {
+    let electors = members(sim, faction, ruler);
+    let mut votes = vec![0_usize; electors.len()];
+
+    for &elector in &electors {
+        let aspects = &sim.entities[elector].aspects;
+        let choice = electors
+            .iter()
+            .enumerate()
+            .filter(|&(_, &candidate)| candidate != elector)
+            .max_by(|(_, a), (_, b)| {
+                let a = aspects.cosine_similarity(&sim.entities[**a].aspects);
+                let b = aspects.cosine_similarity(&sim.entities[**b].aspects);
+                a.total_cmp(&b)
+            });
+        if let Some((idx, _)) = choice {
+            votes[idx] += 1;
+        }
+    }
+
+    electors
+        .iter()
+        .zip(&votes)
+        .max_by(|(a, votes_a), (b, votes_b)| {
+            let born = |id: EntityId| sim.entities[id].life.map(|life| life.born);
+            votes_a.cmp(votes_b).then_with(|| born(**b).cmp(&born(**a)))
+        })
+        .map(|(&id, _)| id)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::life::{self, Life, Sex};
+
+    #[test]
+    fn rulers_dying_in_the_same_turn_are_not_chosen_again() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        // Three adults of an elective faction, the first of them the eldest and so its ruler
+        let faction = sim.entities.lookup("bernicia");
+        let members = members(&sim, faction, EntityId::null());
+        let &[eldest, a, b] = members.as_slice() else {
+            panic!("expected three members, found {}", members.len());
+        };
+        for (id, years) in [(eldest, 60.), (a, 40.), (b, 30.)] {
+            sim.entities[id].life = Some(Life::aged(sim.turn_number, years, Sex::Male));
+        }
+        sim.entities.set_link(faction, LinkName::Ruler, eldest);
+
+        life::die(&mut sim, eldest, &mut rng);
+        let successor = sim.entities[faction].links.get(LinkName::Ruler);
+        assert!([a, b].contains(&successor));
+
+        // The ruler who died first is still around until the end of the system, but is out of
+        // the running, so the one member left rules alone
+        life::die(&mut sim, successor, &mut rng);
+        let last = if successor == a { b } else { a };
+        assert_eq!(sim.entities[faction].links.get(LinkName::Ruler), last);
+
+        spawn::despawn_queued(&mut sim);
+        assert_eq!(sim.entities[faction].links.get(LinkName::Ruler), last);
+        assert_eq!(
+            sim.entities
+                .sources(last, LinkName::Ruler)
+                .collect::<Vec<_>>(),
+            [faction]
+        );
+    }
+}
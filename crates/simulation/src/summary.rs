@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use slotmap::Key;
+
+use crate::entities::*;
+use crate::simulation::Simulation;
+
+/// An overview of the state of a simulation, for tools that run it without a display
+pub struct Summary {
+    pub turn_number: usize,
+    /// How many entities there are of each kind, sorted by kind
+    pub kinds: Vec<(String, usize)>,
+    pub factions: Vec<FactionSummary>,
+    /// The history of the world, oldest first, as `(turn, description)`. Routine events such
+    /// as entities coming and going are left out
+    pub events: Vec<(usize, String)>,
+}
+
+pub struct FactionSummary {
+    pub name: String,
+    /// The faction this one is a part of, if any
+    pub liege: Option<String>,
+    /// How many direct members the faction has of each kind, sorted by kind
+    pub members: Vec<(String, usize)>,
+}
+
+pub(crate) fn summarize(sim: &Simulation) -> Summary {
+    let mut kinds: BTreeMap<&str, usize> = BTreeMap::new();
+    for entity in sim.entities.iter() {
+        *kinds.entry(entity.kind_name).or_default() += 1;
+    }
+
+    let factions = sim
+        .entities
+        .with_flag(Flag::IsFaction)
+        .iter()
+        .map(|&id| &sim.entities[id])
+        .map(|faction| {
+            let mut members: BTreeMap<&str, usize> = BTreeMap::new();
+            for &member in faction.hierarchies.children(HierarchyName::Faction) {
+                *members.entry(sim.entities[member].kind_name).or_default() += 1;
+            }
+
+            let liege = faction.hierarchies.parent(HierarchyName::Faction);
+            FactionSummary {
+                name: faction.name.clone(),
+                liege: (!liege.is_null()).then(|| sim.entities[liege].name.clone()),
+                members: counts(members),
+            }
+        })
+        .collect();
+
+    Summary {
+        turn_number: sim.turn_number,
+        kinds: counts(kinds),
+        factions,
+        events: sim
+            .events
+            .iter()
+            .filter(|event| !event.kind.is_routine())
+            .map(|event| (event.turn, event.text.clone()))
+            .collect(),
+    }
+}
+
+fn counts(map: BTreeMap<&str, usize>) -> Vec<(String, usize)> {
+    map.into_iter()
+        .map(|(kind, count)| (kind.to_string(), count))
+        .collect()
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Turn {}", self.turn_number)?;
+
+        writeln!(f, "Entities:")?;
+        for (kind, count) in &self.kinds {
+            writeln!(f, "  {kind}: {count}")?;
+        }
+
+        writeln!(f, "Factions:")?;
+        for faction in &self.factions {
+            write!(f, "  {}", faction.name)?;
+            if let Some(liege) = &faction.liege {
+                write!(f, " (part of {liege})")?;
+            }
+            writeln!(f)?;
+            for (kind, count) in &faction.members {
+                writeln!(f, "    {kind}: {count}")?;
+            }
+        }
+
+        if !self.events.is_empty() {
+            writeln!(f, "Events:")?;
+            for (turn, text) in &self.events {
+                writeln!(f, "  Turn {turn}: {text}")?;
+            }
+        }
+        Ok(())
+    }
+}
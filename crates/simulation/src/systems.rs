@@ -0,0 +1,193 @@
+use std::time::Instant;
+
+use rand::rngs::SmallRng;
+use strum::{EnumCount, EnumIter, IntoEnumIterator, IntoStaticStr};
+use util::arena::Arena;
+
+use crate::ai;
+use crate::culture;
+use crate::diplomacy;
+use crate::economy;
+use crate::events::EventKind;
+use crate::family;
+use crate::life;
+use crate::movement;
+use crate::population;
+use crate::simulation::Simulation;
+use crate::spawn;
+use crate::succession;
+use crate::view::PhaseTiming;
+
+/// The stages of an end of turn, run in declaration order
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, EnumIter, EnumCount, IntoStaticStr,
+)]
+pub(crate) enum Phase {
+    StartOfTurn,
+    Economy,
+    Movement,
+    Ai,
+    Events,
+    EndOfTurn,
+}
+
+pub(crate) type SystemFn = fn(&mut Simulation, &Arena, &mut SmallRng);
+
+pub(crate) struct System {
+    pub name: &'static str,
+    pub phase: Phase,
+    pub run: SystemFn,
+}
+
+/// The per-turn logic of the simulation, grouped by phase. Within a phase, systems run in the
+/// order they were registered. Each system draws from a random stream of its own, told apart by
+/// its name
+pub(crate) struct Systems {
+    entries: Vec<System>,
+}
+
+impl Default for Systems {
+    fn default() -> Self {
+        let mut systems = Self { entries: vec![] };
+        systems.register("advance_turn", Phase::StartOfTurn, advance_turn);
+        systems.register("production", Phase::Economy, economy::production);
+        systems.register("population", Phase::Economy, population::update);
+        systems.register("movement", Phase::Movement, movement::advance_all);
+        systems.register("ai", Phase::Ai, ai::take_turns);
+        systems.register("culture_drift", Phase::Events, culture::drift);
+        systems.register("ageing", Phase::Events, life::age_all);
+        systems.register("family", Phase::Events, family::update);
+        systems.register("diplomacy", Phase::EndOfTurn, diplomacy::update);
+        systems.register("succession", Phase::EndOfTurn, succession::fill_vacancies);
+        systems
+    }
+}
+
+impl Systems {
+    pub fn register(&mut self, name: &'static str, phase: Phase, run: SystemFn) {
+        assert!(
+            self.entries.iter().all(|system| system.name != name),
+            "system '{name}' is already registered"
+        );
+        let idx = self.entries.partition_point(|system| system.phase <= phase);
+        self.entries.insert(idx, System { name, phase, run });
+    }
+}
+
+/// Runs every registered system once, phase by phase, and reports how long each phase took
+pub(crate) fn run_turn(sim: &mut Simulation, arena: &Arena) -> Vec<PhaseTiming> {
+    let mut timings = Vec::with_capacity(Phase::COUNT);
+
+    sim.record_event(
+        EventKind::TurnEnded,
+        format!("Turn {} ended", sim.turn_number),
+        vec![],
+    );
+
+    for phase in Phase::iter() {
+        let start = Instant::now();
+        let mut systems = vec![];
+
+        // Systems are plain function pointers, so they can be copied out of the registry before
+        // handing the simulation over to them
+        let mut idx = 0;
+        while let Some(system) = sim.systems.entries.get(idx) {
+            idx += 1;
+            if system.phase != phase {
+                continue;
+            }
+            let (name, run) = (system.name, system.run);
+            let mut rng = sim.rngs.system_stream(name);
+            run(sim, arena, &mut rng);
+            spawn::despawn_queued(sim);
+            systems.push(name);
+        }
+
+        timings.push(PhaseTiming {
+            phase: phase.into(),
+            systems,
+            duration: start.elapsed(),
+        });
+    }
+
+    timings
+}
+
+fn advance_turn(sim: &mut Simulation, _: &Arena, _: &mut SmallRng) {
+    sim.turn_number += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn late_start(sim: &mut Simulation, _: &Arena, _: &mut SmallRng) {
+        sim.record_event(EventKind::TurnEnded, "late_start".to_string(), vec![]);
+    }
+
+    fn late_economy(sim: &mut Simulation, _: &Arena, _: &mut SmallRng) {
+        sim.record_event(EventKind::TurnEnded, "late_economy".to_string(), vec![]);
+    }
+
+    #[test]
+    fn systems_run_phase_by_phase_in_the_order_they_were_registered() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        // Registered after everything else, but in phases that come before most of it
+        sim.systems
+            .register("late_economy", Phase::Economy, late_economy);
+        sim.systems
+            .register("late_start", Phase::StartOfTurn, late_start);
+
+        let timings = run_turn(&mut sim, &arena);
+        let phases: Vec<_> = timings.iter().map(|timing| timing.phase).collect();
+        let expected: Vec<&str> = Phase::iter().map(|phase| phase.into()).collect();
+        assert_eq!(phases, expected);
+
+        let systems: Vec<_> = timings
+            .iter()
+            .map(|timing| timing.systems.clone())
+            .collect();
+        assert_eq!(
+            systems,
+            [
+                vec!["advance_turn", "late_start"],
+                vec!["production", "population", "late_economy"],
+                vec!["movement"],
+                vec!["ai"],
+                vec!["culture_drift", "ageing", "family"],
+                vec!["diplomacy", "succession"],
+            ]
+        );
+
+        let ran: Vec<_> = sim
+            .events
+            .iter()
+            .filter(|event| event.text.starts_with("late_"))
+            .map(|event| event.text.as_str())
+            .collect();
+        assert_eq!(ran, ["late_start", "late_economy"]);
+    }
+
+    #[test]
+    fn timings_are_only_reported_for_ticks_that_end_the_turn() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(1, &arena);
+        let view = sim.tick(crate::TickRequest::default(), &arena);
+        assert!(view.turn_timings.is_empty());
+
+        let request = crate::TickRequest {
+            end_turn: true,
+            ..Default::default()
+        };
+        let view = sim.tick(request, &arena);
+        assert_eq!(view.turn_timings.len(), Phase::COUNT);
+    }
+
+    #[test]
+    #[should_panic(expected = "system 'ai' is already registered")]
+    fn systems_are_registered_once() {
+        let mut systems = Systems::default();
+        systems.register("ai", Phase::Events, late_start);
+    }
+}
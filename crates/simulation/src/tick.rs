@@ -1,14 +1,15 @@
-use rand::SeedableRng;
-use rand::rngs::SmallRng;
 use slotmap::Key;
 use spatial::geom::Extents;
 use util::arena::Arena;
-use util::tagged::TaggedCollection;
 
+use crate::actions;
 use crate::entities::*;
+use crate::events::EventKind;
 use crate::object::*;
+use crate::rng::RngStream;
 use crate::simulation::*;
-use crate::spawn::PrototypeArgs;
+use crate::spawn;
+use crate::systems;
 use crate::view;
 
 #[derive(Default)]
@@ -26,13 +27,17 @@ pub struct ViewRequest {
 }
 
 pub(super) fn tick(sim: &mut Simulation, request: TickRequest, arena: &Arena) -> view::SimView {
-    if request.end_turn {
-        sim.turn_number += 1;
-    }
+    let turn_timings = if request.end_turn {
+        systems::run_turn(sim, arena)
+    } else {
+        vec![]
+    };
 
+    // The view the request was made from may show entities that have since despawned
     sim.active_agent = request
         .make_active
         .and_then(|x| x.as_entity())
+        .filter(|&id| sim.entities.contains(id))
         .unwrap_or(sim.active_agent);
 
     refresh_colours(sim);
@@ -41,10 +46,17 @@ pub(super) fn tick(sim: &mut Simulation, request: TickRequest, arena: &Arena) ->
     // Update interaction
     if let Some(object) = request.interacted_with_object {
         handle_interaction(sim, arena, object);
+        spawn::despawn_queued(sim);
+    }
+
+    if cfg!(debug_assertions)
+        && let Err(problem) = sim.entities.audit(&sim.sites)
+    {
+        panic!("entities are inconsistent: {problem}");
     }
 
     // Extract view
-    if request.view.enabled {
+    let mut view = if request.view.enabled {
         view::extract(
             sim,
             arena,
@@ -53,29 +65,31 @@ pub(super) fn tick(sim: &mut Simulation, request: TickRequest, arena: &Arena) ->
         )
     } else {
         view::SimView::default()
-    }
+    };
+    view.turn_timings = turn_timings;
+    view
 }
 
 fn handle_interaction(sim: &mut Simulation, arena: &Arena, interacted_with: ObjectId) {
     let available_actions = std::mem::take(&mut sim.interaction.available_actions);
 
-    let rng = &mut {
-        let seed = (sim.turn_number as u64).wrapping_mul(13).wrapping_add(2732);
-        SmallRng::seed_from_u64(seed)
-    };
+    let rng = &mut sim.rngs.stream(RngStream::Interaction);
     // Update interaction
     match interacted_with.0 {
         ObjectHandle::Null => sim.interaction.selected_entity = EntityId::null(),
-        ObjectHandle::Entity(id) => {
+        ObjectHandle::Entity(id) if sim.entities.contains(id) => {
             sim.interaction.selected_entity = id;
         }
         ObjectHandle::AvailableAction(idx) => {
             let action = available_actions.list.into_iter().nth(idx).unwrap();
 
-            println!("Performing action {}", action.name);
-            if let Some((proto, args)) = action.spawn_prototype.as_ref() {
-                proto.spawn(sim, arena, rng, args);
-            }
+            let (subject, target) = (sim.active_agent, sim.interaction.selected_entity);
+            let text = format!(
+                "{} took the action {} towards {}",
+                sim.entities[subject].name, action.name, sim.entities[target].name
+            );
+            sim.record_event(EventKind::ActionTaken, text, vec![subject, target]);
+            action.perform(sim, arena, rng);
         }
         _ => {}
     };
@@ -91,23 +105,7 @@ fn determine_available_player_actions(sim: &mut Simulation) {
     actions.list.clear();
 
     if has_subject_and_object {
-        actions.list.push(Action {
-            name: "Test Action",
-            ..Default::default()
-        });
-
-        if target.flags.get(Flag::IsLocation) {
-            let prototype = sim.prototypes.lookup("bonheddwr").unwrap_or_default();
-            let args = PrototypeArgs {
-                location: target.id,
-                ..Default::default()
-            };
-
-            actions.list.push(Action {
-                name: "Recruit",
-                spawn_prototype: Some((prototype, args)),
-            })
-        }
+        actions.list = actions::options(sim, subject.id, target.id);
     }
     sim.interaction.available_actions = actions;
 }
@@ -132,3 +130,36 @@ fn refresh_colours(sim: &mut Simulation) {
         color.dirty = is_dirty;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn despawned_entities_cannot_be_selected_or_made_active() {
+        let arena = Arena::default();
+        let mut sim = Simulation::new(3, &arena);
+        let person = sim
+            .entities
+            .iter()
+            .find(|x| x.flags.get(Flag::IsPerson))
+            .unwrap()
+            .id;
+        spawn::despawn(&mut sim, person);
+        spawn::despawn_queued(&mut sim);
+
+        let request = TickRequest {
+            view: ViewRequest {
+                enabled: true,
+                ..Default::default()
+            },
+            make_active: Some(ObjectId::entity(person)),
+            interacted_with_object: Some(ObjectId::entity(person)),
+            ..Default::default()
+        };
+        sim.tick(request, &arena);
+        assert_ne!(sim.active_agent, person);
+        assert_ne!(sim.interaction.selected_entity, person);
+        assert!(sim.entities[person].id.is_null());
+    }
+}
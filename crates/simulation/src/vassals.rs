@@ -0,0 +1,258 @@
+use slotmap::Key;
+
+use crate::diplomacy::{self, DiplomaticState, Relation};
+use crate::entities::*;
+use crate::events::EventKind;
+use crate::simulation::Simulation;
+use crate::spawn;
+
+/// Ways for a faction to change its place in the `Faction` hierarchy
+#[derive(Clone, Copy)]
+pub(crate) enum Realignment {
+    /// Breaks away from the liege, at war with the realm left behind
+    DeclareIndependence,
+    /// Becomes a vassal of another faction, leaving the current liege if any
+    SwearFealty,
+    /// Takes everything a direct vassal holds and does away with it
+    Annex,
+}
+
+/// The faction an entity acts for: itself for factions, otherwise the faction it belongs to
+pub(crate) fn faction_of(sim: &Simulation, entity: EntityId) -> EntityId {
+    let data = &sim.entities[entity];
+    if data.flags.get(Flag::IsFaction) {
+        entity
+    } else {
+        data.hierarchies.parent(HierarchyName::Faction)
+    }
+}
+
+/// The realignments one faction can go through with another
+pub(crate) fn available_realignments(
+    sim: &Simulation,
+    from: EntityId,
+    to: EntityId,
+) -> Vec<Realignment> {
+    let mut out = vec![];
+    if from.is_null() || to.is_null() || from == to {
+        return out;
+    }
+    let liege = sim.entities[from]
+        .hierarchies
+        .parent(HierarchyName::Faction);
+    if liege == to {
+        out.push(Realignment::DeclareIndependence);
+    } else if !sim.entities.is_descendant(HierarchyName::Faction, to, from) {
+        out.push(Realignment::SwearFealty);
+    }
+    if sim.entities[to].hierarchies.parent(HierarchyName::Faction) == from {
+        out.push(Realignment::Annex);
+    }
+    out
+}
+
+impl Realignment {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DeclareIndependence => "Declare Independence",
+            Self::SwearFealty => "Swear Fealty",
+            Self::Annex => "Annex",
+        }
+    }
+
+    /// Carries out the realignment of one faction towards another, returning whether it went
+    /// through. Nothing changes if it would make a faction its own liege
+    pub fn perform(self, sim: &mut Simulation, from: EntityId, to: EntityId) -> bool {
+        match self {
+            Self::DeclareIndependence => declare_independence(sim, from),
+            Self::SwearFealty => swear_fealty(sim, from, to),
+            Self::Annex => annex(sim, from, to),
+        }
+    }
+}
+
+/// Has the faction leave its liege and stand as a realm of its own, at war with its former one
+pub(crate) fn declare_independence(sim: &mut Simulation, faction: EntityId) -> bool {
+    let liege = sim.entities[faction]
+        .hierarchies
+        .parent(HierarchyName::Faction);
+    if liege.is_null() {
+        return false;
+    }
+    let former_realm = diplomacy::realm_of(sim, faction);
+    set_liege(sim, faction, EntityId::null());
+
+    let relation = Relation {
+        state: DiplomaticState::War {
+            since: sim.turn_number,
+        },
+        ..sim.diplomacy.get(faction, former_realm)
+    };
+    sim.diplomacy.set(faction, former_realm, relation);
+
+    let text = format!(
+        "{} rose up against {} and declared independence",
+        sim.entities[faction].name, sim.entities[liege].name
+    );
+    sim.record_event(EventKind::IndependenceDeclared, text, vec![faction, liege]);
+    true
+}
+
+/// Makes the faction a vassal of the liege, unless the liege is the faction itself or one of
+/// its own vassals
+pub(crate) fn swear_fealty(sim: &mut Simulation, faction: EntityId, liege: EntityId) -> bool {
+    if liege.is_null() || !set_liege(sim, faction, liege) {
+        return false;
+    }
+    let text = format!(
+        "{} swore fealty to {}",
+        sim.entities[faction].name, sim.entities[liege].name
+    );
+    sim.record_event(EventKind::FealtySworn, text, vec![faction, liege]);
+    true
+}
+
+/// Hands every member of a direct vassal over to its liege, capital included if the liege has
+/// none, and removes the vassal
+pub(crate) fn annex(sim: &mut Simulation, liege: EntityId, vassal: EntityId) -> bool {
+    if sim.entities[vassal]
+        .hierarchies
+        .parent(HierarchyName::Faction)
+        != liege
+        || liege.is_null()
+    {
+        return false;
+    }
+
+    let members = sim.entities[vassal]
+        .hierarchies
+        .children(HierarchyName::Faction)
+        .to_vec();
+    for member in members {
+        sim.entities
+            .set_parent(HierarchyName::Faction, member, liege)
+            .expect("the liege of a vassal is not below its members");
+        mark_colours_dirty(&mut sim.entities, member);
+    }
+
+    let seat = sim.entities[vassal]
+        .hierarchies
+        .parent(HierarchyName::Capital);
+    if !seat.is_null()
+        && sim.entities[liege]
+            .hierarchies
+            .parent(HierarchyName::Capital)
+            .is_null()
+    {
+        sim.entities
+            .set_parent(HierarchyName::Capital, liege, seat)
+            .expect("locations are not held as capitals");
+    }
+
+    let text = format!(
+        "{} annexed {}",
+        sim.entities[liege].name, sim.entities[vassal].name
+    );
+    sim.record_event(EventKind::Annexed, text, vec![liege, vassal]);
+
+    spawn::despawn(sim, vassal);
+    true
+}
+
+/// Moves the faction under the given liege, or makes it a realm if the liege is null. Refuses
+/// moves that would make the faction a vassal of itself
+fn set_liege(sim: &mut Simulation, faction: EntityId, liege: EntityId) -> bool {
+    if liege.is_null() {
+        sim.entities.unparent(HierarchyName::Faction, faction);
+    } else if sim
+        .entities
+        .set_parent(HierarchyName::Faction, faction, liege)
+        .is_ok()
+    {
+        // Vassals have no foreign policy of their own
+        diplomacy::forget(sim, faction);
+    } else {
+        return false;
+    }
+    mark_colours_dirty(&mut sim.entities, faction);
+    true
+}
+
+/// Has every entity from this one down the `Faction` hierarchy pick its colour anew
+pub(crate) fn mark_colours_dirty(entities: &mut Entities, root: EntityId) {
+    let mut stack = vec![root];
+    while let Some(id) = stack.pop() {
+        let entity = &mut entities[id];
+        if entity.color.dynamic {
+            entity.color.dirty = true;
+        }
+        stack.extend_from_slice(entity.hierarchies.children(HierarchyName::Faction));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use util::arena::Arena;
+
+    use super::*;
+
+    fn liege(sim: &Simulation, faction: EntityId) -> EntityId {
+        sim.entities[faction]
+            .hierarchies
+            .parent(HierarchyName::Faction)
+    }
+
+    #[test]
+    fn no_faction_becomes_its_own_liege() {
+        let mut sim = Simulation::new(1, &Arena::default());
+        let rheged = sim.entities.lookup("rheged");
+        let drust = sim.entities.lookup("clan_drust");
+        let heledd = sim.entities.lookup("clan_heledd");
+
+        // A liege cannot swear fealty to its own vassal, nor to a vassal of one
+        assert!(
+            !available_realignments(&sim, rheged, drust)
+                .iter()
+                .any(|x| matches!(x, Realignment::SwearFealty))
+        );
+        assert!(!Realignment::SwearFealty.perform(&mut sim, rheged, drust));
+        assert!(swear_fealty(&mut sim, heledd, drust));
+        assert!(!swear_fealty(&mut sim, rheged, heledd));
+        assert_eq!(liege(&sim, rheged), EntityId::null());
+        assert!(sim.entities.audit(&sim.sites).is_ok());
+
+        // Only direct vassals can be annexed, and realms have no liege to break away from
+        assert!(!annex(&mut sim, rheged, heledd));
+        assert!(!annex(&mut sim, drust, rheged));
+        assert!(!declare_independence(&mut sim, rheged));
+        assert_eq!(liege(&sim, heledd), drust);
+    }
+
+    #[test]
+    fn independence_starts_a_war_and_annexation_hands_over_the_members() {
+        let mut sim = Simulation::new(1, &Arena::default());
+        let rheged = sim.entities.lookup("rheged");
+        let drust = sim.entities.lookup("clan_drust");
+        let heledd = sim.entities.lookup("clan_heledd");
+
+        assert!(declare_independence(&mut sim, drust));
+        assert_eq!(liege(&sim, drust), EntityId::null());
+        assert!(matches!(
+            sim.diplomacy.get(drust, rheged).state,
+            DiplomaticState::War { .. }
+        ));
+
+        let members = sim.entities[heledd]
+            .hierarchies
+            .children(HierarchyName::Faction)
+            .to_vec();
+        assert!(!members.is_empty());
+        assert!(annex(&mut sim, rheged, heledd));
+        for member in members {
+            assert_eq!(liege(&sim, member), rheged);
+        }
+        spawn::despawn_queued(&mut sim);
+        assert!(!sim.entities.contains(heledd));
+        assert!(sim.entities.audit(&sim.sites).is_ok());
+    }
+}
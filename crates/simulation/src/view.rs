@@ -1,23 +1,40 @@
 use slotmap::Key;
 use spatial::geom::*;
 use std::borrow::Borrow;
+use std::time::Duration;
+use strum::IntoEnumIterator;
 use util::arena::Arena;
 
+use crate::aspects::{self, AspectVector};
+use crate::diplomacy;
+use crate::economy::{Economy, Good};
 use crate::entities;
 use crate::entities::*;
+use crate::family;
 use crate::object::*;
+use crate::query::Query;
 use crate::simulation::*;
-use crate::sites::Sites;
+use crate::sites::{SiteId, Sites};
 
-#[derive(Default)]
+#[derive(Default, PartialEq, Debug)]
 pub struct SimView {
     pub map_lines: Vec<(V2, V2)>,
     pub map_items: Vec<MapItem>,
     pub root: Object,
     pub selected: Object,
+    /// How long each phase of the turn took, filled in on ticks that end the turn
+    pub turn_timings: Vec<PhaseTiming>,
 }
 
-#[derive(Clone, Copy)]
+#[derive(PartialEq, Debug)]
+pub struct PhaseTiming {
+    pub phase: &'static str,
+    /// Systems that ran in the phase, in order
+    pub systems: Vec<&'static str>,
+    pub duration: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct RGB {
     pub r: u8,
     pub g: u8,
@@ -34,6 +51,7 @@ impl Default for RGB {
     }
 }
 
+#[derive(PartialEq, Debug)]
 pub struct MapItem {
     pub id: ObjectId,
     pub name: String,
@@ -43,6 +61,9 @@ pub struct MapItem {
     pub size: f32,
     pub layer: u8,
     pub highlight: bool,
+    /// How large to draw the name relative to the usual size, such as bigger for more populous
+    /// locations
+    pub label_scale: f32,
 }
 
 pub(super) fn extract(
@@ -78,6 +99,8 @@ fn map_view_lines(sites: &Sites, viewport: Extents) -> Vec<(V2, V2)> {
     out
 }
 
+const IDLE_PARTY_OFFSET: f32 = 1.2;
+
 fn map_view_items(sim: &Simulation, viewport: Extents) -> Vec<MapItem> {
     let sites = sim.sites.data.values().filter_map(|site| {
         if !site.bound_entity.is_null() {
@@ -100,32 +123,82 @@ fn map_view_items(sim: &Simulation, viewport: Extents) -> Vec<MapItem> {
             size: 1.,
             layer: 0,
             highlight: false,
+            label_scale: 1.,
         })
     });
 
-    let locations = sim.entities.iter().filter_map(|entity| {
-        let pos = sim.sites.pos_of(entity.bound_site);
-        if !viewport.contains(pos) {
-            return None;
-        }
-        let highlight = entity.id == sim.interaction.selected_entity;
-        Some(MapItem {
-            id: ObjectId(ObjectHandle::Entity(entity.id)),
-            name: entity.name.clone(),
-            color: entity.color.current,
-            image: entity.sprite,
-            pos,
-            size: entity.size,
-            layer: 1,
-            highlight,
-        })
-    });
+    let locations = sim
+        .entities
+        .with_flag(Flag::IsPlace)
+        .iter()
+        .filter_map(|&id| {
+            let entity = &sim.entities[id];
+            let pos = sim.sites.pos_of(entity.bound_site);
+            if !viewport.contains(pos) {
+                return None;
+            }
+            let highlight = entity.id == sim.interaction.selected_entity;
+            Some(MapItem {
+                id: ObjectId(ObjectHandle::Entity(entity.id)),
+                name: entity.name.clone(),
+                color: entity.color.current,
+                image: entity.sprite,
+                pos,
+                size: entity.size,
+                layer: 1,
+                highlight,
+                label_scale: entity
+                    .population
+                    .as_ref()
+                    .map(|population| population_label_scale(population.total()))
+                    .unwrap_or(1.),
+            })
+        });
+
+    // Parties are always shown, people only while on the road
+    let travellers = [Flag::IsParty, Flag::IsPerson]
+        .into_iter()
+        .flat_map(|flag| sim.entities.with_flag(flag))
+        .filter_map(|&id| {
+            let entity = &sim.entities[id];
+            let movement = entity.movement.as_ref()?;
+            if !movement.is_travelling() && !entity.flags.get(Flag::IsParty) {
+                return None;
+            }
+            let mut pos = movement.position(&sim.sites);
+            if !movement.is_travelling() {
+                // Keep parties staying at a site from hiding under its location
+                pos.x += IDLE_PARTY_OFFSET;
+                pos.y -= IDLE_PARTY_OFFSET;
+            }
+            if !viewport.contains(pos) {
+                return None;
+            }
+            let highlight = entity.id == sim.interaction.selected_entity;
+            Some(MapItem {
+                id: ObjectId(ObjectHandle::Entity(entity.id)),
+                name: entity.name.clone(),
+                color: entity.color.current,
+                image: entity.sprite,
+                pos,
+                size: entity.size,
+                layer: 2,
+                highlight,
+                label_scale: 1.,
+            })
+        });
 
-    let mut items: Vec<_> = sites.chain(locations).collect();
+    let mut items: Vec<_> = sites.chain(locations).chain(travellers).collect();
     items.sort_by_key(|item| item.layer);
     items
 }
 
+/// Grows with the square root of the population, so that labels stay readable at both ends
+fn population_label_scale(population: f64) -> f32 {
+    const REFERENCE: f64 = 300.;
+    (population / REFERENCE).sqrt().clamp(0.75, 1.75) as f32
+}
+
 fn extract_object(sim: &Simulation, arena: &Arena, id: ObjectId) -> Object {
     match id.0 {
         ObjectHandle::Global => {
@@ -154,6 +227,7 @@ fn extract_object(sim: &Simulation, arena: &Arena, id: ObjectId) -> Object {
                         .collect::<Vec<_>>(),
                 );
             }
+            obj.set("events", extract_events(sim));
             obj
         }
 
@@ -195,12 +269,29 @@ fn extract_entity(sim: &Simulation, arena: &Arena, subject: &EntityData) -> Obje
 
         let faction = subject.hierarchies.parent(HierarchyName::Faction);
         if !faction.is_null() {
-            obj.set("faction", &sim.entities[faction].name);
+            obj.set("faction", ruled_name(sim, faction));
+        }
+
+        let culture = subject.links.get(LinkName::Culture);
+        if !culture.is_null() {
+            obj.set("culture", &sim.entities[culture].name);
+        }
+        let religion = subject.links.get(LinkName::Religion);
+        if !religion.is_null() {
+            obj.set("religion", &sim.entities[religion].name);
         }
 
         let reign = sim.entities.root_of(HierarchyName::Faction, subject.id);
         if !reign.is_null() {
-            obj.set("reign", &sim.entities[reign].name);
+            obj.set("reign", ruled_name(sim, reign));
+        }
+
+        if let Some(law) = subject.succession {
+            let ruler = subject.links.get(LinkName::Ruler);
+            if !ruler.is_null() {
+                obj.set("ruler", &sim.entities[ruler].name);
+            }
+            obj.set("succession", law.name());
         }
 
         obj.set(
@@ -212,35 +303,243 @@ fn extract_entity(sim: &Simulation, arena: &Arena, subject: &EntityData) -> Obje
             ),
         );
 
+        if let Some(movement) = &subject.movement
+            && movement.is_travelling()
+        {
+            obj.set("destination", site_name(sim, movement.destination()));
+        }
+
+        if subject.flags.get(Flag::IsPerson) {
+            let dynasty = subject.hierarchies.parent(HierarchyName::Dynasty);
+            if !dynasty.is_null() {
+                obj.set("dynasty", &sim.entities[dynasty].name);
+            }
+            let parents = family::parents(&sim.entities, subject.id);
+            let spouse = subject.links.get(LinkName::Spouse);
+            obj.set(
+                "parents",
+                extract_reference_list_from_ids(sim, parents.iter().filter(|id| !id.is_null())),
+            );
+            obj.set(
+                "spouse",
+                extract_reference_list_from_ids(sim, [spouse].iter().filter(|id| !id.is_null())),
+            );
+            obj.set(
+                "children",
+                extract_reference_list_from_ids(sim, family::children(&sim.entities, subject.id)),
+            );
+        }
+
+        // Links that may point at several entities, and those pointing back at this one
+        let allies: Vec<EntityId> = subject.links.all(LinkName::Ally).collect();
+        if !allies.is_empty() {
+            obj.set("allies", extract_reference_list_from_ids(sim, allies));
+        }
+        let holds: Vec<EntityId> = sim.entities.sources(subject.id, LinkName::Ruler).collect();
+        if !holds.is_empty() {
+            obj.set("holds", extract_reference_list_from_ids(sim, holds));
+        }
+        if matches!(subject.kind_name, "Culture" | "Religion") {
+            let link = if subject.kind_name == "Culture" {
+                LinkName::Culture
+            } else {
+                LinkName::Religion
+            };
+            obj.set(
+                "followers",
+                extract_reference_list_from_ids(sim, sim.entities.sources(subject.id, link)),
+            );
+        }
+
+        if subject.flags.get(Flag::IsDynasty) {
+            let members = Query::new(sim)
+                .children_of(HierarchyName::Dynasty, subject.id)
+                .run(arena);
+            obj.set("members", extract_reference_list(members.iter().copied()));
+        }
+
+        if let Some(life) = &subject.life {
+            let age = life.age(sim.turn_number);
+            obj.set("age", format!("{:.0}", age.floor()));
+            obj.set("born", format!("turn {}", life.born));
+            obj.set("sex", life.sex.name());
+            obj.set("health", format!("{:.0}%", life.health * 100.));
+        }
+
         obj.set(
             "can_make_active_agent",
-            subject.flags.get(entities::Flag::IsPerson),
+            subject.flags.get(entities::Flag::IsPerson) || subject.movement.is_some(),
         );
 
         if subject.flags.get(Flag::IsPlace) {
             obj.set("people_here", {
-                let list = sim.entities.children_with_flags(
-                    subject,
-                    HierarchyName::PlaceOf,
-                    &[entities::Flag::IsPerson],
-                );
-                extract_reference_list(list)
+                let list = Query::new(sim)
+                    .children_of(HierarchyName::PlaceOf, subject.id)
+                    .with_flags(&[Flag::IsPerson])
+                    .run(arena);
+                extract_reference_list(list.iter().copied())
             });
 
             obj.set("cards_here", {
-                let list = sim.entities.children_with_flags(
-                    subject,
-                    HierarchyName::PlaceOf,
-                    &[entities::Flag::IsCard],
-                );
-                extract_reference_list(list)
+                let list = Query::new(sim)
+                    .children_of(HierarchyName::PlaceOf, subject.id)
+                    .with_flags(&[Flag::IsCard])
+                    .run(arena);
+                extract_reference_list(list.iter().copied())
+            });
+
+            obj.set("parties_here", {
+                let list = Query::new(sim)
+                    .children_of(HierarchyName::PlaceOf, subject.id)
+                    .with_flags(&[Flag::IsParty])
+                    .run(arena);
+                extract_reference_list(list.iter().copied())
             });
         }
+
+        if subject.flags.get(Flag::IsFaction) {
+            obj.set("relations", extract_relations(sim, reign));
+        }
+
+        let profile = aspects::profile(&sim.entities, subject.id);
+        if profile.sum() != 0. {
+            obj.set("aspects", extract_aspects(sim, &profile));
+        }
+
+        // How closely the place resembles the realm it belongs to
+        if subject.flags.get(Flag::IsPlace) && !reign.is_null() {
+            let realm = aspects::profile(&sim.entities, reign);
+            let cohesion = profile.cosine_similarity(&realm);
+            obj.set("cohesion", format!("{:.0}%", cohesion * 100.));
+        }
+
+        if let Some(economy) = &subject.economy {
+            obj.set("stockpile", extract_stockpile(economy));
+        }
+
+        if let Some(population) = &subject.population {
+            obj.set("population", format!("{:.0}", population.total()));
+            obj.set(
+                "population_change",
+                format!(
+                    "{:+.0} (born {:.0}, starved {:.0}, migrated {:+.0})",
+                    population.born - population.starved + population.migrated,
+                    population.born,
+                    population.starved,
+                    population.migrated
+                ),
+            );
+            obj.set(
+                "population_by_culture",
+                population
+                    .by_culture
+                    .iter()
+                    .map(|(culture, heads)| {
+                        let mut obj = Object::new();
+                        obj.set("name", &sim.aspects[culture].name);
+                        obj.set("amount", format!("{heads:.0}"));
+                        obj
+                    })
+                    .collect::<Vec<_>>(),
+            );
+        }
     }
 
     obj
 }
 
+/// The name of a faction along with that of its ruler, if it has one
+fn ruled_name(sim: &Simulation, faction: EntityId) -> String {
+    let ruler = sim.entities[faction].links.get(LinkName::Ruler);
+    if ruler.is_null() {
+        sim.entities[faction].name.clone()
+    } else {
+        format!(
+            "{} (ruled by {})",
+            sim.entities[faction].name, sim.entities[ruler].name
+        )
+    }
+}
+
+/// How the realm stands with every other realm
+fn extract_relations(sim: &Simulation, realm: EntityId) -> Vec<Object> {
+    diplomacy::realms(sim)
+        .into_iter()
+        .filter(|&other| other != realm)
+        .map(|other| {
+            let relation = sim.diplomacy.get(realm, other);
+            let mut obj = Object::new();
+            obj.set("id", ObjectId::entity(other));
+            obj.set("name", &sim.entities[other].name);
+            obj.set("state", relation.state.name());
+            obj.set("score", format!("{:+.0}", relation.score));
+            obj
+        })
+        .collect()
+}
+
+fn extract_aspects(sim: &Simulation, profile: &AspectVector) -> Vec<Object> {
+    let mut entries: Vec<_> = profile.iter().collect();
+    entries.sort_by(|a, b| b.1.total_cmp(&a.1));
+    entries
+        .into_iter()
+        .map(|(aspect, share)| {
+            let mut obj = Object::new();
+            obj.set("name", &sim.aspects[aspect].name);
+            obj.set("share", format!("{:.0}%", share * 100.));
+            obj
+        })
+        .collect()
+}
+
+fn extract_stockpile(economy: &Economy) -> Vec<Object> {
+    Good::iter()
+        .filter(|&good| economy.stockpile.get(good) != 0. || economy.flow.get(good) != 0.)
+        .map(|good| {
+            let mut obj = Object::new();
+            obj.set("name", good.name());
+            obj.set("amount", format!("{:.1}", economy.stockpile.get(good)));
+            obj.set("flow", format!("{:+.1}", economy.flow.get(good)));
+            obj.set("shortfall", format!("{:.1}", economy.shortfall.get(good)));
+            obj
+        })
+        .collect()
+}
+
+/// Name of the location at a site, or the tag of the site when there is none
+fn site_name(sim: &Simulation, site: SiteId) -> &str {
+    let location = sim.sites.data[site].bound_entity;
+    if location.is_null() {
+        sim.sites.tags.reverse_lookup(&site).unwrap_or_default()
+    } else {
+        &sim.entities[location].name
+    }
+}
+
+/// The event history, oldest first. Subjects that are no longer around are left out, as there
+/// is nothing left to select
+fn extract_events(sim: &Simulation) -> Vec<Object> {
+    sim.events
+        .iter()
+        .map(|event| {
+            let mut obj = Object::new();
+            obj.set("turn", format!("{}", event.turn));
+            obj.set("kind", <&'static str>::from(event.kind));
+            obj.set("text", &event.text);
+            obj.set(
+                "subjects",
+                extract_reference_list(
+                    event
+                        .subjects
+                        .iter()
+                        .filter_map(|&subject| sim.entities.get(subject)),
+                ),
+            );
+            obj
+        })
+        .collect()
+}
+
 #[inline]
 fn extract_reference_list_from_ids<'a, T: Borrow<EntityId>>(
     sim: &Simulation,
@@ -66,6 +66,16 @@ impl<K: Key> Graph2D<K> {
         self.distances.insert((min_id, max_id), distance);
     }
 
+    /// Adds `id2` to the neighbours of `id1`, but not the other way around. Meant for rebuilding
+    /// a graph while preserving the order of each neighbour list: once both ends have been
+    /// connected, the result is the same as that of `connect`
+    pub fn connect_one_way(&mut self, id1: K, id2: K) {
+        let distance = self.nodes[id1].pos.distance(self.nodes[id2].pos);
+        Self::insert_no_repeat(&mut self.nodes[id1].neighbours, id2, distance);
+        self.distances
+            .insert((id1.min(id2), id1.max(id2)), distance);
+    }
+
     fn insert_no_repeat(vs: &mut Vec<Neighbour<K>>, id: K, distance: f32) {
         if vs.iter().all(|x| x.id != id) {
             vs.push(Neighbour { id, distance });
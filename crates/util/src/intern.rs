@@ -0,0 +1,15 @@
+use std::collections::HashSet;
+use std::sync::{LazyLock, Mutex};
+
+static STRINGS: LazyLock<Mutex<HashSet<&'static str>>> = LazyLock::new(Default::default);
+
+/// Returns a `'static` copy of the string, leaking at most one copy per distinct value
+pub fn intern(source: &str) -> &'static str {
+    let mut strings = STRINGS.lock().unwrap();
+    if let Some(&existing) = strings.get(source) {
+        return existing;
+    }
+    let leaked: &'static str = source.to_string().leak();
+    strings.insert(leaked);
+    leaked
+}
@@ -1,6 +1,7 @@
 pub mod arena;
 pub mod enum_map;
 pub mod hierarchy;
+pub mod intern;
 pub mod misc;
 pub mod one_to_one_map;
 pub mod tagged;
@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 
+#[derive(Clone)]
 pub struct Tally<K: Copy + Ord>(BTreeMap<K, f64>);
 
 impl<K: Copy + Ord> Default for Tally<K> {